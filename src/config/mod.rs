@@ -1,6 +1,7 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use derivative::Derivative;
 use duration_string::DurationString;
+use itertools::Itertools;
 use std::path::PathBuf;
 use thiserror::Error;
 use tokio::fs;
@@ -8,7 +9,7 @@ use tracing::instrument;
 
 use crate::error::SpanErr;
 
-use self::s3_target::Config;
+use self::s3_target::{Config, WriteQuorum};
 
 pub mod s3_target;
 
@@ -21,6 +22,9 @@ pub mod s3_target;
     about = "A transparent proxy for S3 replication"
 )]
 pub(crate) struct AppArgs {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(long)]
     pub config_file: PathBuf,
 
@@ -35,6 +39,83 @@ pub(crate) struct AppArgs {
 
     #[clap(long, default_value = "5s")]
     pub stream_stall_grace_period: DurationString,
+
+    /// Secret for signing ListObjectsV2 continuation tokens. When set, tokens are stateless instead of stored in MongoDB
+    #[clap(long, env = "LIST_TOKEN_SECRET", hide_env_values = true)]
+    #[derivative(Debug = "ignore")]
+    pub list_token_secret: Option<String>,
+
+    /// How long a signed continuation token stays valid
+    #[clap(long, default_value = "1h")]
+    pub list_token_ttl: DurationString,
+
+    /// Interval between background health checks of each remote
+    #[clap(long, default_value = "10s")]
+    pub health_check_interval: DurationString,
+
+    /// Interval between garbage collection runs for stale multipart uploads
+    #[clap(long, default_value = "1h")]
+    pub multipart_gc_interval: DurationString,
+
+    /// Multipart uploads neither completed nor aborted within this age are aborted
+    #[clap(long, default_value = "7d")]
+    pub multipart_gc_max_age: DurationString,
+
+    /// Interval between runs of the repair worker for inconsistent writes
+    #[clap(long, default_value = "30s")]
+    pub repair_interval: DurationString,
+
+    /// Delay before a failed backfill is resumed from its last checkpoint
+    #[clap(long, default_value = "1m")]
+    pub backfill_retry_interval: DurationString,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Copy every object into a remote from the other remotes, then exit
+    Backfill {
+        /// Name of the remote to backfill
+        remote: String,
+
+        /// Start over instead of resuming from the last checkpoint
+        #[clap(long)]
+        restart: bool,
+    },
+
+    /// Print a presigned URL for an object on this proxy, then exit
+    Presign(PresignArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct PresignArgs {
+    /// HTTP method the URL is for
+    #[clap(value_enum)]
+    pub method: PresignMethod,
+
+    pub bucket: String,
+
+    pub key: String,
+
+    /// How long the URL stays valid, up to 7 days
+    #[clap(long, default_value = "15m")]
+    pub expires_in: DurationString,
+
+    /// URL clients reach the proxy at. Its host is signed, so it must be the one clients send
+    #[clap(long, default_value = "http://localhost:9000")]
+    pub endpoint: String,
+
+    /// Access key to sign with, from the config file. The top-level access_key when omitted
+    #[clap(long)]
+    pub access_key: Option<String>,
+
+    #[clap(long, default_value = "us-east-1")]
+    pub region: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum PresignMethod {
+    Get,
+    Put,
 }
 
 #[derive(Debug)]
@@ -51,8 +132,38 @@ pub(crate) enum Error {
     #[error("Failed to parse config file {0}:\n {1}")]
     Serde(PathBuf, #[source] serde_yaml::Error),
 
-    #[error("At least one readable target must be specified")]
-    MissingReadableTarget,
+    #[error("At least one bucket must be specified")]
+    MissingBucket,
+
+    #[error("Top-level remotes are specified without bucket")]
+    RemotesWithoutBucket,
+
+    #[error("Bucket {0:?} is declared more than once")]
+    DuplicateBucket(String),
+
+    #[error("Target {0:?} is declared more than once")]
+    DuplicateTarget(String),
+
+    #[error("Access key {0:?} is declared more than once")]
+    DuplicateAccessKey(String),
+
+    #[error("At least one readable target must be specified (bucket: {0:?})")]
+    MissingReadableTarget(String),
+
+    #[error("write_quorum must be between 1 and the number of targets ({1}), but got {2} (bucket: {0:?})")]
+    InvalidWriteQuorum(String, usize, usize),
+
+    #[error("Routing rule refers to unknown target {1:?} (bucket: {0:?})")]
+    UnknownRuleTarget(String, String),
+
+    #[error("Routing rule must route to at least one target (bucket: {0:?})")]
+    EmptyRuleTargets(String),
+
+    #[error("{0} must be greater than zero")]
+    ZeroInterval(&'static str),
+
+    #[error("Scrub interval must be greater than zero (target: {0:?})")]
+    ZeroScrubInterval(String),
 }
 
 impl S3ReproxySetup {
@@ -72,18 +183,89 @@ impl S3ReproxySetup {
         Ok(setup)
     }
 
-    /// TODO: 名前の重複に対してエラーを出
     #[instrument(name = "setup/validation")]
     fn validate_config(setup: &Self) -> Result<(), SpanErr<Error>> {
-        if setup
+        // Background workers tick on these, and a zero-length interval panics.
+        for (name, interval) in [
+            ("--health-check-interval", &setup.args.health_check_interval),
+            ("--multipart-gc-interval", &setup.args.multipart_gc_interval),
+            ("--repair-interval", &setup.args.repair_interval),
+            (
+                "--backfill-retry-interval",
+                &setup.args.backfill_retry_interval,
+            ),
+        ] {
+            if interval.is_zero() {
+                Err(Error::ZeroInterval(name))?;
+            }
+        }
+
+        if setup.config.bucket.is_none() && !setup.config.remotes.is_empty() {
+            Err(Error::RemotesWithoutBucket)?;
+        }
+
+        let buckets = setup.config.buckets();
+        if buckets.is_empty() {
+            Err(Error::MissingBucket)?;
+        }
+        if let Some(name) = buckets.iter().map(|b| &b.name).duplicates().next() {
+            Err(Error::DuplicateBucket(name.clone()))?;
+        }
+        // Remotes are identified by name alone, e.g. in repair tasks and checkpoints.
+        if let Some(name) = setup.config.targets().map(|t| &t.name).duplicates().next() {
+            Err(Error::DuplicateTarget(name.clone()))?;
+        }
+        if let Some(target) = setup
             .config
-            .remotes
-            .iter()
-            .filter(|t| t.read_request)
-            .count()
-            < 1
+            .targets()
+            .find(|t| t.scrub.as_ref().is_some_and(|s| s.interval.is_zero()))
         {
-            Err(Error::MissingReadableTarget)?;
+            Err(Error::ZeroScrubInterval(target.name.clone()))?;
+        }
+
+        if let Some(access_key) = std::iter::once(&setup.config.access_key)
+            .chain(setup.config.access_keys.iter().map(|k| &k.access_key))
+            .duplicates()
+            .next()
+        {
+            Err(Error::DuplicateAccessKey(access_key.clone()))?;
+        }
+
+        for bucket in buckets {
+            if bucket
+                .remotes
+                .iter()
+                .filter(|t| t.read_request && !t.backfill)
+                .count()
+                < 1
+            {
+                Err(Error::MissingReadableTarget(bucket.name.clone()))?;
+            }
+
+            let targets = bucket.remotes.len();
+            if let WriteQuorum::Count(count) = bucket.policy.write_quorum {
+                if count < 1 || count > targets {
+                    Err(Error::InvalidWriteQuorum(
+                        bucket.name.clone(),
+                        targets,
+                        count,
+                    ))?;
+                }
+            }
+
+            for rule in &bucket.policy.rules {
+                for names in [&rule.write, &rule.read].into_iter().flatten() {
+                    if names.is_empty() {
+                        Err(Error::EmptyRuleTargets(bucket.name.clone()))?;
+                    }
+                    if let Some(name) = names
+                        .iter()
+                        .find(|name| !bucket.remotes.iter().any(|t| &t.name == *name))
+                    {
+                        Err(Error::UnknownRuleTarget(bucket.name.clone(), name.clone()))?;
+                    }
+                }
+            }
         }
 
         Ok(())
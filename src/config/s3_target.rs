@@ -1,14 +1,370 @@
+use std::time::Duration;
+
 use derivative::Derivative;
+use duration_string::DurationString;
 use serde::{Deserialize, Serialize};
 
 #[derive(Derivative, Clone, Serialize, Deserialize, PartialEq)]
 #[derivative(Debug)]
 pub struct Config {
+    /// Targets of `bucket`.
+    #[serde(default)]
     pub remotes: Vec<S3Target>,
     pub access_key: String,
     #[derivative(Debug = "ignore")]
     pub secret_key: String,
-    pub bucket: String,
+    /// Name of the bucket backed by `remotes`. May be omitted when only `buckets` are used.
+    #[serde(default)]
+    pub bucket: Option<String>,
+
+    /// Policies of `bucket`.
+    #[serde(flatten)]
+    pub policy: BucketPolicy,
+
+    /// Further virtual buckets, each backed by its own targets.
+    #[serde(default)]
+    pub buckets: Vec<BucketConfig>,
+
+    /// Further keys clients may sign with. `access_key` itself has every permission on every object.
+    #[serde(default)]
+    pub access_keys: Vec<AccessKey>,
+}
+
+impl Config {
+    /// Every virtual bucket, starting with the top-level `bucket`.
+    pub fn buckets(&self) -> Vec<BucketConfig> {
+        let top = self.bucket.as_ref().map(|name| BucketConfig {
+            name: name.clone(),
+            remotes: self.remotes.clone(),
+            policy: self.policy.clone(),
+        });
+        top.into_iter()
+            .chain(self.buckets.iter().cloned())
+            .collect()
+    }
+
+    /// Every target of every bucket.
+    pub fn targets(&self) -> impl Iterator<Item = &S3Target> {
+        self.remotes
+            .iter()
+            .chain(self.buckets.iter().flat_map(|b| b.remotes.iter()))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Derivative)]
+#[derivative(Debug)]
+pub struct AccessKey {
+    pub access_key: String,
+    #[derivative(Debug = "ignore")]
+    pub secret_key: String,
+
+    /// Operations the key may perform. A key with only `read` is read-only.
+    pub permissions: Vec<Permission>,
+
+    /// Key prefixes the key is limited to. Every key when empty.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+}
+
+impl AccessKey {
+    /// A key with every permission on every object.
+    pub fn full(access_key: String, secret_key: String) -> Self {
+        AccessKey {
+            access_key,
+            secret_key,
+            permissions: vec![Permission::Read, Permission::Write, Permission::Delete],
+            prefixes: vec![],
+        }
+    }
+
+    /// Whether the key has every permission on every key.
+    pub fn is_unrestricted(&self) -> bool {
+        self.prefixes.is_empty()
+            && [Permission::Read, Permission::Write, Permission::Delete]
+                .iter()
+                .all(|p| self.permissions.contains(p))
+    }
+
+    /// Whether the key may perform `permission` on `key`, or on the bucket itself when `key` is `None`.
+    /// For listings, `key` is the listed prefix.
+    pub fn allows(&self, permission: Permission, key: Option<&str>) -> bool {
+        if !self.permissions.contains(&permission) {
+            return false;
+        }
+        match key {
+            Some(key) if !self.prefixes.is_empty() => {
+                self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+            }
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BucketConfig {
+    /// The bucket name clients use.
+    pub name: String,
+
+    pub remotes: Vec<S3Target>,
+
+    #[serde(flatten)]
+    pub policy: BucketPolicy,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BucketPolicy {
+    /// Number of targets that must succeed for a write to succeed, or `all`.
+    #[serde(default)]
+    pub write_quorum: WriteQuorum,
+
+    /// Limits on copying objects into remotes that were missing them on read.
+    #[serde(default)]
+    pub read_repair: ReadRepairConfig,
+
+    /// Also ask the next read target when a read is slow. Disabled when omitted.
+    #[serde(default)]
+    pub hedge: Option<HedgeConfig>,
+
+    /// How reads are spread over targets of the same priority.
+    #[serde(default)]
+    pub routing: RoutingPolicy,
+
+    /// Whether ListObjectsV2 is served by a single target or merged from all healthy read targets.
+    #[serde(default)]
+    pub list_mode: ListMode,
+
+    /// Ordered rules selecting targets by key. The first matching rule applies, and keys matching none use every target.
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingRule {
+    #[serde(flatten)]
+    pub pattern: KeyPattern,
+
+    /// Targets receiving writes. Every target when omitted.
+    #[serde(default)]
+    pub write: Option<Vec<String>>,
+
+    /// Targets eligible for reads. The `write` targets when omitted.
+    #[serde(default)]
+    pub read: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPattern {
+    Prefix(String),
+    /// `*` matches any characters including `/`, and `?` matches a single character.
+    Glob(String),
+}
+
+impl RoutingRule {
+    pub fn matches(&self, key: &str) -> bool {
+        match &self.pattern {
+            KeyPattern::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            KeyPattern::Glob(glob) => glob_match(glob, key),
+        }
+    }
+
+    pub fn writes_to(&self, target: &str) -> bool {
+        self.write
+            .as_ref()
+            .map_or(true, |write| write.iter().any(|t| t == target))
+    }
+
+    pub fn reads_from(&self, target: &str) -> bool {
+        match &self.read {
+            Some(read) => read.iter().any(|t| t == target),
+            None => self.writes_to(target),
+        }
+    }
+}
+
+/// The rule applying to `key`, if any.
+pub fn rule_for<'a>(rules: &'a [RoutingRule], key: &str) -> Option<&'a RoutingRule> {
+    rules.iter().find(|rule| rule.matches(key))
+}
+
+/// The rule applying to every key under `prefix`, if it is certain without looking at the keys.
+pub fn rule_for_prefix<'a>(rules: &'a [RoutingRule], prefix: &str) -> Option<&'a RoutingRule> {
+    for rule in rules {
+        match &rule.pattern {
+            KeyPattern::Prefix(p) if prefix.starts_with(p.as_str()) => return Some(rule),
+            KeyPattern::Prefix(p) if !p.starts_with(prefix) => continue,
+            // The rule applies to some of the keys only.
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// `*` matches any characters including `/`, and `?` matches a single character.
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let key = key.chars().collect::<Vec<_>>();
+
+    let (mut p, mut k) = (0, 0);
+    // Position of the last `*` and the key position it is currently matched up to.
+    let mut star = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, k));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match star {
+                Some((star_p, star_k)) => {
+                    star = Some((star_p, star_k + 1));
+                    p = star_p + 1;
+                    k = star_k + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListMode {
+    /// From the first target that answers, in read order.
+    #[default]
+    Single,
+    /// Merged by key from every healthy read target.
+    Merged,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingPolicy {
+    /// Always in the configured order.
+    #[default]
+    Static,
+    /// Fastest first, by the moving average of latency and error rate.
+    Latency,
+    /// Rotated on every read.
+    RoundRobin,
+}
+
+const fn default_max_hedges() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HedgeConfig {
+    /// How long to wait for an answer before also asking the next read target.
+    pub delay: DurationString,
+
+    /// Maximum number of additional targets asked per read.
+    #[serde(default = "default_max_hedges")]
+    pub max_hedges: usize,
+}
+
+const fn default_read_repair_max_object_size() -> i64 {
+    16 * 1024 * 1024
+}
+
+const fn default_read_repair_concurrency() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ReadRepairConfig {
+    /// Objects larger than this many bytes are not repaired.
+    #[serde(default = "default_read_repair_max_object_size")]
+    pub max_object_size: i64,
+
+    /// Maximum number of repairs in progress at once. Further repairs are skipped until one finishes.
+    #[serde(default = "default_read_repair_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for ReadRepairConfig {
+    fn default() -> Self {
+        Self {
+            max_object_size: default_read_repair_max_object_size(),
+            concurrency: default_read_repair_concurrency(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(from = "WriteQuorumRepr", into = "WriteQuorumRepr")]
+pub enum WriteQuorum {
+    Count(usize),
+    All,
+}
+
+impl Default for WriteQuorum {
+    fn default() -> Self {
+        Self::Count(1)
+    }
+}
+
+impl WriteQuorum {
+    /// Number of successes needed out of `total` remotes.
+    pub fn needed(&self, total: usize) -> usize {
+        match self {
+            // A routing rule may leave fewer targets than the quorum.
+            Self::Count(count) => (*count).min(total),
+            Self::All => total,
+        }
+    }
+
+    /// Whether a write to `expected` remotes succeeded on enough of them, including every `required` one.
+    pub fn is_met(&self, succeeded: &[String], expected: &[String], required: &[String]) -> bool {
+        required
+            .iter()
+            .filter(|r| expected.contains(r))
+            .all(|r| succeeded.contains(r))
+            && succeeded.len() >= self.needed(expected.len())
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+enum WriteQuorumRepr {
+    Count(usize),
+    Keyword(WriteQuorumKeyword),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WriteQuorumKeyword {
+    All,
+}
+
+impl From<WriteQuorumRepr> for WriteQuorum {
+    fn from(repr: WriteQuorumRepr) -> Self {
+        match repr {
+            WriteQuorumRepr::Count(count) => Self::Count(count),
+            WriteQuorumRepr::Keyword(WriteQuorumKeyword::All) => Self::All,
+        }
+    }
+}
+
+impl From<WriteQuorum> for WriteQuorumRepr {
+    fn from(quorum: WriteQuorum) -> Self {
+        match quorum {
+            WriteQuorum::Count(count) => Self::Count(count),
+            WriteQuorum::All => Self::Keyword(WriteQuorumKeyword::All),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Derivative)]
@@ -29,6 +385,10 @@ const fn default_read_request() -> bool {
     true
 }
 
+const fn default_scrub_pages_per_second() -> u32 {
+    10
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct S3Target {
     /// The name of the target
@@ -45,9 +405,67 @@ pub struct S3Target {
     #[serde(default = "default_read_request")]
     pub read_request: bool,
 
+    /// Periodically compare this target against the other targets.
+    /// Disabled when omitted.
+    #[serde(default)]
+    pub scrub: Option<ScrubConfig>,
+
+    /// Copy every object into this target from the other targets, and keep it out of reads until that is done.
+    #[serde(default)]
+    pub backfill: bool,
+
+    /// Writes fail unless this target succeeds, regardless of `write_quorum`.
+    #[serde(default)]
+    pub required: bool,
+
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
     pub s3: S3Credential,
 }
 
+const fn default_circuit_failure_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_open_duration() -> DurationString {
+    DurationString::from(Duration::from_secs(30))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive transport failures after which requests to this target fail fast.
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// How long requests fail fast before a single probe request is let through.
+    #[serde(default = "default_circuit_open_duration")]
+    pub open_duration: DurationString,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_circuit_failure_threshold(),
+            open_duration: default_circuit_open_duration(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScrubConfig {
+    /// Interval between scrubs of this target.
+    pub interval: DurationString,
+
+    /// Maximum number of listing pages requested from each target per second while scrubbing.
+    #[serde(default = "default_scrub_pages_per_second")]
+    pub pages_per_second: u32,
+
+    /// Whether to fix discrepancies by copying the object from the highest-priority target that has it.
+    #[serde(default)]
+    pub repair: bool,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,6 +489,10 @@ mod tests {
                 name: "cloudflare-r2".to_string(),
                 priority: 1,
                 read_request: true,
+                scrub: None,
+                backfill: false,
+                required: false,
+                circuit_breaker: CircuitBreakerConfig::default(),
                 s3: S3Credential {
                     endpoint: "http://localhost:8080".to_string(),
                     access_key: "abcabc".to_string(),
@@ -81,10 +503,195 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_target_with_scrub() {
+        let yaml = r#"
+            name: local-minio
+            scrub:
+              interval: 1d
+              repair: true
+            s3:
+              endpoint: http://localhost:8080
+              access_key: abcabc
+              secret_key: defdef
+              bucket: test
+        "#;
+
+        let target: S3Target = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            target.scrub,
+            Some(ScrubConfig {
+                interval: "1d".parse().unwrap(),
+                pages_per_second: 10,
+                repair: true,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_write_quorum() {
+        assert_eq!(
+            serde_yaml::from_str::<WriteQuorum>("2").unwrap(),
+            WriteQuorum::Count(2)
+        );
+        assert_eq!(
+            serde_yaml::from_str::<WriteQuorum>("all").unwrap(),
+            WriteQuorum::All
+        );
+        assert!(serde_yaml::from_str::<WriteQuorum>("most").is_err());
+    }
+
+    #[test]
+    fn write_quorum_needed() {
+        assert_eq!(WriteQuorum::Count(2).needed(3), 2);
+        assert_eq!(WriteQuorum::Count(3).needed(2), 2);
+        assert_eq!(WriteQuorum::All.needed(3), 3);
+        assert_eq!(WriteQuorum::All.needed(0), 0);
+    }
+
+    #[test]
+    fn write_quorum_is_met() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let expected = names(&["a", "b", "c"]);
+
+        assert!(WriteQuorum::Count(2).is_met(&names(&["a", "b"]), &expected, &[]));
+        assert!(!WriteQuorum::Count(2).is_met(&names(&["a"]), &expected, &[]));
+        assert!(!WriteQuorum::All.is_met(&names(&["a", "b"]), &expected, &[]));
+        assert!(WriteQuorum::All.is_met(&expected, &expected, &[]));
+
+        // Required remotes must succeed regardless of the count.
+        assert!(!WriteQuorum::Count(1).is_met(&names(&["a", "b"]), &expected, &names(&["c"])));
+        assert!(WriteQuorum::Count(1).is_met(&names(&["c"]), &expected, &names(&["c"])));
+        // Required remotes outside of the write set do not count.
+        assert!(WriteQuorum::Count(1).is_met(&names(&["a"]), &expected, &names(&["d"])));
+    }
+
+    #[test]
+    fn parse_read_repair_with_default() {
+        let yaml = r#"
+            max_object_size: 1048576
+        "#;
+
+        let read_repair: ReadRepairConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            read_repair,
+            ReadRepairConfig {
+                max_object_size: 1048576,
+                concurrency: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_routing_policy() {
+        assert_eq!(
+            serde_yaml::from_str::<RoutingPolicy>("round_robin").unwrap(),
+            RoutingPolicy::RoundRobin
+        );
+        assert_eq!(
+            serde_yaml::from_str::<RoutingPolicy>("latency").unwrap(),
+            RoutingPolicy::Latency
+        );
+    }
+
+    #[test]
+    fn parse_config_with_buckets() {
+        let yaml = r#"
+            access_key: abcabc
+            secret_key: defdef
+            bucket: main
+            write_quorum: all
+            remotes:
+            - name: main-minio
+              s3:
+                endpoint: http://localhost:8080
+                access_key: abcabc
+                secret_key: defdef
+                bucket: main
+            buckets:
+            - name: logs
+              routing: round_robin
+              remotes:
+              - name: logs-minio
+                s3:
+                  endpoint: http://localhost:8080
+                  access_key: abcabc
+                  secret_key: defdef
+                  bucket: logs
+        "#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let buckets = config.buckets();
+
+        assert_eq!(
+            buckets.iter().map(|b| b.name.as_str()).collect::<Vec<_>>(),
+            vec!["main", "logs"]
+        );
+        assert_eq!(buckets[0].policy.write_quorum, WriteQuorum::All);
+        assert_eq!(buckets[0].remotes[0].name, "main-minio");
+        assert_eq!(buckets[1].policy.write_quorum, WriteQuorum::Count(1));
+        assert_eq!(buckets[1].policy.routing, RoutingPolicy::RoundRobin);
+        assert_eq!(buckets[1].remotes[0].name, "logs-minio");
+    }
+
+    #[test]
+    fn parse_routing_rules() {
+        let yaml = r#"
+            - prefix: tmp/
+              write: [local-minio]
+            - glob: "archive/*.tar"
+              write: [cold]
+              read: [cold, local-minio]
+        "#;
+
+        let rules: Vec<RoutingRule> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(rules[0].pattern, KeyPattern::Prefix("tmp/".to_string()));
+        assert_eq!(
+            rules[1].pattern,
+            KeyPattern::Glob("archive/*.tar".to_string())
+        );
+
+        assert!(rule_for(&rules, "tmp/a").is_some_and(|r| r.reads_from("local-minio")));
+        assert!(rule_for(&rules, "archive/2024/a.tar").is_some_and(|r| !r.writes_to("local-minio")));
+        assert!(rule_for(&rules, "archive/2024/a.tar").is_some_and(|r| r.reads_from("local-minio")));
+        assert!(rule_for(&rules, "archive/a.tar.gz").is_none());
+        assert!(rule_for(&rules, "data/a").is_none());
+
+        assert_eq!(rule_for_prefix(&rules, "tmp/x/"), Some(&rules[0]));
+        assert_eq!(rule_for_prefix(&rules, "archive/"), None);
+        assert_eq!(rule_for_prefix(&rules, "data/"), None);
+    }
+
+    #[test]
+    fn parse_access_keys() {
+        let yaml = r#"
+            - access_key: reader
+              secret_key: abcabc
+              permissions: [read]
+              prefixes: [public/]
+            - access_key: writer
+              secret_key: defdef
+              permissions: [read, write]
+        "#;
+
+        let keys: Vec<AccessKey> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(keys[0].permissions, vec![Permission::Read]);
+
+        assert!(keys[0].allows(Permission::Read, Some("public/a")));
+        assert!(keys[0].allows(Permission::Read, None));
+        assert!(!keys[0].allows(Permission::Read, Some("private/a")));
+        assert!(!keys[0].allows(Permission::Write, Some("public/a")));
+        assert!(keys[1].allows(Permission::Write, Some("private/a")));
+        assert!(!keys[1].allows(Permission::Delete, Some("private/a")));
+    }
+
     #[test]
     fn parse_config() {
         let yaml = r#"
-            targets:
+            access_key: proxy
+            secret_key: proxy-secret
+            bucket: test
+            remotes:
             - name: cloudflare-r2
               priority: 3
               read_request: false
@@ -112,6 +719,10 @@ mod tests {
                     name: "cloudflare-r2".to_string(),
                     priority: 3,
                     read_request: false,
+                    scrub: None,
+                    backfill: false,
+                    required: false,
+                    circuit_breaker: CircuitBreakerConfig::default(),
                     s3: S3Credential {
                         endpoint: "http://localhost:8080".to_string(),
                         access_key: "abcabc".to_string(),
@@ -123,6 +734,10 @@ mod tests {
                     name: "local-minio".to_string(),
                     priority: 5,
                     read_request: true,
+                    scrub: None,
+                    backfill: false,
+                    required: false,
+                    circuit_breaker: CircuitBreakerConfig::default(),
                     s3: S3Credential {
                         endpoint: "http://localhost:8080".to_string(),
                         access_key: "abcabc".to_string(),
@@ -1,11 +1,13 @@
 use std::time::Duration;
 
 use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
 use mongodb::options::{ClientOptions, IndexOptions};
 use mongodb::IndexModel;
 use serde::{Deserialize, Serialize};
 use tracing::{info, instrument};
 
+use crate::config::s3_target::AccessKey;
 use crate::error::SpanErr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +19,12 @@ pub struct ListObjectTokens {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultipartUploadIds {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Virtual bucket of the upload. Documents written before this field existed have none.
+    pub bucket: Option<String>,
+    /// Object key of the upload. Documents written before this field existed have none.
+    pub key: Option<String>,
     pub upload_ids: Vec<RemoteMultipartUploadId>,
     pub created_at: mongodb::bson::DateTime,
     pub completed_at: Option<mongodb::bson::DateTime>,
@@ -38,6 +46,14 @@ impl RemoteMultipartUploadId {
             upload_id: self.upload_id.clone(),
         }
     }
+
+    pub fn aborted(&self) -> RemoteMultipartUploadId {
+        RemoteMultipartUploadId {
+            status: PartUploadStatus::Aborted,
+            remote_name: self.remote_name.clone(),
+            upload_id: self.upload_id.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -45,6 +61,111 @@ impl RemoteMultipartUploadId {
 pub enum PartUploadStatus {
     Open,
     Cancelled,
+    /// The remote upload has been aborted on the remote itself.
+    Aborted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairTask {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub key: String,
+    pub operation: RepairOperation,
+    /// Proxy upload id, for operations on a multipart upload.
+    pub upload_id: Option<ObjectId>,
+    pub succeeded_remotes: Vec<String>,
+    pub failed_remotes: Vec<String>,
+    pub attempts: i32,
+    pub created_at: mongodb::bson::DateTime,
+    pub next_attempt_at: mongodb::bson::DateTime,
+    pub resolved_at: Option<mongodb::bson::DateTime>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairOperation {
+    PutObject,
+    CopyObject,
+    DeleteObject,
+    DeleteObjects,
+    UploadPart,
+    UploadPartCopy,
+    CompleteMultipartUpload,
+    PutObjectTagging,
+    DeleteObjectTagging,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubDiscrepancy {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// The scrubbed remote.
+    pub remote: String,
+    pub key: String,
+    pub kind: DiscrepancyKind,
+    /// The highest-priority remote that has the object.
+    pub reference_remote: String,
+    pub expected_e_tag: Option<String>,
+    pub expected_size: Option<i64>,
+    pub actual_e_tag: Option<String>,
+    pub actual_size: Option<i64>,
+    pub detected_at: mongodb::bson::DateTime,
+    pub repaired_at: Option<mongodb::bson::DateTime>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    Missing,
+    Mismatch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// The backfilled remote.
+    pub remote: String,
+    /// Source remote being listed.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Last key of the last copied listing page of `source`. Backfill resumes after it.
+    pub start_after: Option<String>,
+    /// Source remotes which have been listed to the end.
+    #[serde(default)]
+    pub completed_sources: Vec<String>,
+    pub copied: i64,
+    pub started_at: mongodb::bson::DateTime,
+    pub updated_at: mongodb::bson::DateTime,
+    pub completed_at: Option<mongodb::bson::DateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketPolicyDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// The virtual bucket.
+    pub bucket: String,
+    /// The policy document as put by the client, returned as-is by GetBucketPolicy.
+    pub policy: String,
+    pub updated_at: mongodb::bson::DateTime,
+}
+
+impl RepairOperation {
+    /// Whether the failed remotes are repaired by deleting the key rather than copying it.
+    pub fn is_delete(&self) -> bool {
+        matches!(self, Self::DeleteObject | Self::DeleteObjects)
+    }
+
+    /// Whether only the tag set of the object is repaired.
+    pub fn is_tagging(&self) -> bool {
+        matches!(self, Self::PutObjectTagging | Self::DeleteObjectTagging)
+    }
+
+    /// Whether the object only exists once the multipart upload is completed.
+    pub fn is_part(&self) -> bool {
+        matches!(self, Self::UploadPart | Self::UploadPartCopy)
+    }
 }
 
 pub struct MongoDB {
@@ -53,9 +174,32 @@ pub struct MongoDB {
 
     pub list_object_tokens: mongodb::Collection<ListObjectTokens>,
     pub multipart_upload_ids: mongodb::Collection<MultipartUploadIds>,
+    pub repair_tasks: mongodb::Collection<RepairTask>,
+    pub scrub_discrepancies: mongodb::Collection<ScrubDiscrepancy>,
+    pub backfill_checkpoints: mongodb::Collection<BackfillCheckpoint>,
+    /// Access keys managed outside of the config file.
+    pub access_keys: mongodb::Collection<AccessKey>,
+    pub bucket_policies: mongodb::Collection<BucketPolicyDocument>,
 }
 
 impl MongoDB {
+    /// Collections of `db_name`, without creating their indexes.
+    /// The client connects lazily, so nothing is sent to the server until a collection is used.
+    pub fn new(client: mongodb::Client, db_name: &str) -> Self {
+        let db = client.database(db_name);
+        Self {
+            client,
+            list_object_tokens: db.collection("list_object_tokens"),
+            multipart_upload_ids: db.collection("multipart_upload_ids"),
+            repair_tasks: db.collection("repair_tasks"),
+            scrub_discrepancies: db.collection("scrub_discrepancies"),
+            backfill_checkpoints: db.collection("backfill_checkpoints"),
+            access_keys: db.collection("access_keys"),
+            bucket_policies: db.collection("bucket_policies"),
+            db,
+        }
+    }
+
     #[instrument(name = "mongodb/connect", skip_all)]
     pub async fn connect(
         uri: String,
@@ -63,16 +207,9 @@ impl MongoDB {
     ) -> Result<MongoDB, SpanErr<mongodb::error::Error>> {
         let client_options = ClientOptions::parse(uri).await?;
         let client = mongodb::Client::with_options(client_options)?;
-        let db = client.database(db_name.as_str());
+        let mongo = Self::new(client, &db_name);
         info!("Connected to MongoDB ({}).", db_name);
 
-        let mongo = Self {
-            client,
-            list_object_tokens: db.collection("list_object_tokens"),
-            multipart_upload_ids: db.collection("multipart_upload_ids"),
-            db,
-        };
-
         info!("Creating indexes...");
 
         mongo
@@ -107,6 +244,91 @@ impl MongoDB {
 
         info!("list_object_tokens consumed_at index created.");
 
+        mongo
+            .multipart_upload_ids
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "key": 1, "_id": 1 })
+                    .build(),
+            )
+            .await?;
+
+        info!("multipart_upload_ids key index created.");
+
+        mongo
+            .repair_tasks
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "resolved_at": 1, "next_attempt_at": 1 })
+                    .build(),
+            )
+            .await?;
+
+        info!("repair_tasks next_attempt_at index created.");
+
+        mongo
+            .repair_tasks
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "resolved_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_days(7))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        info!("repair_tasks resolved_at index created.");
+
+        mongo
+            .scrub_discrepancies
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "remote": 1, "key": 1, "repaired_at": 1 })
+                    .build(),
+            )
+            .await?;
+
+        info!("scrub_discrepancies remote index created.");
+
+        mongo
+            .backfill_checkpoints
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "remote": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+
+        info!("backfill_checkpoints remote index created.");
+
+        mongo
+            .access_keys
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "access_key": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+
+        info!("access_keys access_key index created.");
+
+        mongo
+            .bucket_policies
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "bucket": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+            )
+            .await?;
+
+        info!("bucket_policies bucket index created.");
+
         info!("Indexes created.");
 
         Ok(mongo)
@@ -2,16 +2,27 @@
 #![feature(duration_constructors)]
 use std::net::Ipv4Addr;
 use std::sync::Arc;
+use std::time::SystemTime;
 
+use crate::config::s3_target::AccessKey;
+use crate::server::auth::ReproxyAuth;
+use crate::server::bucket::VirtualBucket;
+use crate::server::presign::{presign, PresignError};
 use crate::server::remote::spawn_remote;
-use crate::server::S3Reproxy;
+use crate::server::token::ListTokenSigner;
+use crate::server::{ClientAddr, S3Reproxy};
+use crate::worker::backfill::{run_backfill, spawn_backfill, BackfillError};
+use crate::worker::multipart_gc::spawn_multipart_gc;
+use crate::worker::repair::spawn_repair;
+use crate::worker::scrub::spawn_scrubber;
 use clap::Parser;
+use hyper::body::Incoming;
+use hyper::service::{service_fn, Service};
 use hyper_util::rt::{TokioExecutor, TokioIo};
-use s3s::auth::SimpleAuth;
 use s3s::service::S3ServiceBuilder;
 use tokio::net::TcpListener;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::task::JoinSet;
 use tower::ServiceBuilder;
 use tracing_subscriber::filter::filter_fn;
@@ -19,6 +30,7 @@ pub mod config;
 pub mod db;
 pub mod error;
 pub mod server;
+pub mod worker;
 
 use self::config::S3ReproxySetup;
 use self::error::SpanErr;
@@ -77,6 +89,12 @@ enum S3ProxyError {
 
     #[error("Failed to connect to MongoDB: \n{0}")]
     DB(#[from] mongodb::error::Error),
+
+    #[error("Failed to backfill: \n{0}")]
+    Backfill(#[from] BackfillError),
+
+    #[error("Failed to presign: \n{0}")]
+    Presign(#[from] PresignError),
 }
 
 #[instrument]
@@ -87,26 +105,97 @@ async fn s3_reproxy() -> Result<(), SpanErr<S3ProxyError>> {
         .await
         .map_err(|e| e.map(S3ProxyError::Setup))?;
 
+    if let Some(config::Command::Presign(args)) = &setup.args.command {
+        let url = presign(&setup.config, args, SystemTime::now())
+            .await
+            .map_err(S3ProxyError::Presign)?;
+        println!("{}", url);
+        return Ok(());
+    }
+
     let mut remote_tasks = JoinSet::new();
+    let buckets = setup.config.buckets();
     let remotes = Arc::new(
-        setup
-            .config
-            .remotes
+        buckets
             .iter()
-            .map(|t| spawn_remote(t.clone(), &setup, &mut remote_tasks))
-            .collect(),
+            .flat_map(|b| b.remotes.iter().map(|t| (b.name.clone(), t.clone())))
+            .map(|(bucket, t)| spawn_remote(bucket, t, &setup, &mut remote_tasks))
+            .collect::<Vec<_>>(),
     );
 
     let db = Arc::new(
-        db::MongoDB::connect(setup.args.mongo_uri, setup.args.mongo_db)
+        db::MongoDB::connect(setup.args.mongo_uri.clone(), setup.args.mongo_db.clone())
             .await
             .map_err(|e| e.map(S3ProxyError::DB))?,
     );
 
+    if let Some(config::Command::Backfill { remote, restart }) = &setup.args.command {
+        let result = run_backfill(&remotes, &db, &setup.config, remote, *restart).await;
+
+        for r in remotes.iter() {
+            r.tx.send(server::remote::RemoteMessage::Shutdown)
+                .await
+                .map_err(S3ProxyError::Remote)?;
+        }
+        while (remote_tasks.join_next().await).is_some() {}
+
+        result.map_err(S3ProxyError::Backfill)?;
+        info!("Backfill complete");
+
+        return Ok(());
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut worker_tasks = JoinSet::new();
+    spawn_multipart_gc(
+        Arc::clone(&remotes),
+        Arc::clone(&db),
+        &setup,
+        shutdown_rx.clone(),
+        &mut worker_tasks,
+    );
+    spawn_repair(
+        Arc::clone(&remotes),
+        Arc::clone(&db),
+        &setup,
+        shutdown_rx.clone(),
+        &mut worker_tasks,
+    );
+    spawn_scrubber(
+        Arc::clone(&remotes),
+        Arc::clone(&db),
+        &setup,
+        shutdown_rx.clone(),
+        &mut worker_tasks,
+    );
+    spawn_backfill(
+        Arc::clone(&remotes),
+        Arc::clone(&db),
+        &setup,
+        shutdown_rx.clone(),
+        &mut worker_tasks,
+    );
+
+    let auth = ReproxyAuth::new(
+        std::iter::once(AccessKey::full(
+            setup.config.access_key,
+            setup.config.secret_key,
+        ))
+        .chain(setup.config.access_keys)
+        .collect(),
+        Arc::clone(&db),
+    );
+
     let server = S3Reproxy {
-        bucket: setup.config.bucket,
+        buckets: buckets.into_iter().map(VirtualBucket::new).collect(),
         remotes: Arc::clone(&remotes),
         db,
+        list_tokens: setup
+            .args
+            .list_token_secret
+            .as_deref()
+            .map(|secret| ListTokenSigner::new(secret, *setup.args.list_token_ttl)),
+        auth: auth.clone(),
     };
 
     for r in remotes.iter() {
@@ -119,10 +208,7 @@ async fn s3_reproxy() -> Result<(), SpanErr<S3ProxyError>> {
 
     let s3_service = {
         let mut builder = S3ServiceBuilder::new(server);
-        builder.set_auth(SimpleAuth::from_single(
-            setup.config.access_key,
-            setup.config.secret_key,
-        ));
+        builder.set_auth(auth);
         builder.build()
     };
 
@@ -151,10 +237,17 @@ async fn s3_reproxy() -> Result<(), SpanErr<S3ProxyError>> {
             res = listener.accept() => {
 
                 match res {
-                    Ok((stream, _)) => {
+                    Ok((stream, addr)) => {
                         let peer = stream.peer_addr().ok().map(|a| format!("{:?}", a));
+                        // Bucket policies may have conditions on the client address, i.e. `aws:SourceIp`.
+                        // It is the TCP peer, so behind a load balancer or reverse proxy it is the address of the balancer.
+                        let service = hyper_s3_service.clone();
+                        let service = service_fn(move |mut req: hyper::Request<Incoming>| {
+                            req.extensions_mut().insert(ClientAddr(addr));
+                            service.call(req)
+                        });
                         let serve = graceful.watch(
-                            http_server.serve_connection(TokioIo::new(stream), hyper_s3_service.clone()).into_owned()
+                            http_server.serve_connection(TokioIo::new(stream), service).into_owned()
                         );
                         tokio::spawn(async move {
                             let _ = serve.await;
@@ -172,6 +265,9 @@ async fn s3_reproxy() -> Result<(), SpanErr<S3ProxyError>> {
         }
     }
 
+    let _ = shutdown_tx.send(true);
+    while (worker_tasks.join_next().await).is_some() {}
+
     for r in remotes.iter() {
         r.tx.send(server::remote::RemoteMessage::Shutdown)
             .await
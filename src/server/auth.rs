@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use s3s::auth::{S3Auth, SecretKey};
+use s3s::{s3_error, S3Error, S3ErrorCode, S3Result};
+use tracing::{error, warn};
+
+use crate::config::s3_target::AccessKey;
+use crate::db::MongoDB;
+
+/// SigV4 does not allow presigned URLs valid for longer than a week.
+const MAX_PRESIGNED_EXPIRES: i64 = 7 * 24 * 60 * 60;
+
+/// Access keys from the config, then from the `access_keys` collection.
+/// Keys in the collection are looked up on each request, so they can be added or revoked without a restart.
+#[derive(Clone)]
+pub struct ReproxyAuth {
+    keys: Arc<Vec<AccessKey>>,
+    db: Arc<MongoDB>,
+}
+
+impl ReproxyAuth {
+    pub fn new(keys: Vec<AccessKey>, db: Arc<MongoDB>) -> Self {
+        ReproxyAuth {
+            keys: Arc::new(keys),
+            db,
+        }
+    }
+
+    pub async fn access_key(&self, access_key: &str) -> S3Result<Option<AccessKey>> {
+        if let Some(key) = self.keys.iter().find(|k| k.access_key == access_key) {
+            return Ok(Some(key.clone()));
+        }
+
+        self.db
+            .access_keys
+            .find_one(doc! { "access_key": access_key })
+            .await
+            .map_err(|e| {
+                error!("Failed to find access key: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })
+    }
+}
+
+#[async_trait]
+impl S3Auth for ReproxyAuth {
+    async fn get_secret_key(&self, access_key: &str) -> S3Result<SecretKey> {
+        match self.access_key(access_key).await? {
+            Some(key) => Ok(SecretKey::from(key.secret_key)),
+            None => {
+                warn!("(intercepted) unknown access key: {}", access_key);
+                Err(s3_error!(InvalidAccessKeyId))
+            }
+        }
+    }
+}
+
+/// Rejects presigned requests valid for longer than SigV4 allows, which s3s does not check.
+/// s3s has already rejected expired or malformed presigned requests, and requests authenticated with the Authorization header are left alone.
+pub fn check_presigned_expires(uri: &http::Uri) -> S3Result<()> {
+    match presigned_expires(uri.query().unwrap_or_default()) {
+        Some(expires) if expires > MAX_PRESIGNED_EXPIRES => {
+            warn!("(intercepted) X-Amz-Expires too long: {}", expires);
+            Err(S3Error::with_message(
+                S3ErrorCode::Custom("AuthorizationQueryParametersError".into()),
+                "X-Amz-Expires must be less than a week (in seconds) that is 604800",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `X-Amz-Expires` of a presigned request, in seconds.
+fn presigned_expires(query: &str) -> Option<i64> {
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v)
+    };
+
+    param("X-Amz-Signature")?;
+    param("X-Amz-Expires")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn check(query: &str) -> S3Result<()> {
+        check_presigned_expires(&format!("/bucket/key?{}", query).parse().unwrap())
+    }
+
+    #[test]
+    fn presigned_expires_is_capped_at_a_week() {
+        assert!(check("X-Amz-Expires=604800&X-Amz-Signature=abc").is_ok());
+
+        let e = check("X-Amz-Expires=604801&X-Amz-Signature=abc").unwrap_err();
+        assert_eq!(
+            e.code(),
+            &S3ErrorCode::Custom("AuthorizationQueryParametersError".into())
+        );
+    }
+
+    #[test]
+    fn presigned_expires_leaves_other_requests_to_s3s() {
+        // Not presigned.
+        assert!(check("X-Amz-Expires=604801").is_ok());
+        assert!(check("").is_ok());
+        // Malformed, which s3s rejects.
+        assert_eq!(
+            presigned_expires("X-Amz-Expires=soon&X-Amz-Signature=abc"),
+            None
+        );
+        assert!(check("X-Amz-Expires=soon&X-Amz-Signature=abc").is_ok());
+    }
+}
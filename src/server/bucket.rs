@@ -0,0 +1,80 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+
+use crate::config::s3_target::{
+    rule_for, rule_for_prefix, BucketConfig, HedgeConfig, ListMode, ReadRepairConfig,
+    RoutingPolicy, RoutingRule, WriteQuorum,
+};
+
+use super::policy::PolicyDocument;
+
+/// How long a bucket policy read from MongoDB is used before it is read again, so that policies put through other replicas take effect.
+const POLICY_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// A bucket exposed by the proxy, backed by the remotes whose `bucket` is its name.
+pub struct VirtualBucket {
+    pub name: String,
+    pub write_quorum: WriteQuorum,
+    pub read_repair: ReadRepairConfig,
+    pub read_repair_permits: Arc<Semaphore>,
+    pub hedge: Option<HedgeConfig>,
+    pub routing: RoutingPolicy,
+    pub read_turn: AtomicUsize,
+    pub list_mode: ListMode,
+    pub rules: Vec<RoutingRule>,
+    /// The bucket policy last read from MongoDB or put through this replica, and when.
+    policy: RwLock<Option<(Instant, Option<Arc<PolicyDocument>>)>>,
+}
+
+impl VirtualBucket {
+    pub fn new(config: BucketConfig) -> Self {
+        let policy = config.policy;
+        VirtualBucket {
+            name: config.name,
+            write_quorum: policy.write_quorum,
+            read_repair: policy.read_repair,
+            read_repair_permits: Arc::new(Semaphore::new(policy.read_repair.concurrency)),
+            hedge: policy.hedge,
+            routing: policy.routing,
+            read_turn: AtomicUsize::new(0),
+            list_mode: policy.list_mode,
+            rules: policy.rules,
+            policy: RwLock::new(None),
+        }
+    }
+
+    /// The cached bucket policy, or `None` if it has to be read again.
+    pub fn cached_policy(&self) -> Option<Option<Arc<PolicyDocument>>> {
+        let cached = self.policy.read().unwrap();
+        let (cached_at, policy) = cached.as_ref()?;
+        (cached_at.elapsed() < POLICY_CACHE_TTL).then(|| policy.clone())
+    }
+
+    pub fn cache_policy(&self, policy: Option<Arc<PolicyDocument>>) {
+        *self.policy.write().unwrap() = Some((Instant::now(), policy));
+    }
+
+    /// The routing rule applying to `key`.
+    pub fn rule(&self, key: &str) -> Option<&RoutingRule> {
+        rule_for(&self.rules, key)
+    }
+
+    /// The routing rule applying to a listing of `prefix`, if the whole listing falls under a single rule.
+    pub fn list_rule(&self, prefix: Option<&str>) -> Option<&RoutingRule> {
+        rule_for_prefix(&self.rules, prefix.unwrap_or_default())
+    }
+
+    /// How to list `prefix`.
+    /// A listing spanning several rules is merged even in single mode, as no remote has to hold every key of it.
+    pub fn list_mode_for(&self, prefix: Option<&str>) -> ListMode {
+        match self.list_mode {
+            ListMode::Single if !self.rules.is_empty() && self.list_rule(prefix).is_none() => {
+                ListMode::Merged
+            }
+            mode => mode,
+        }
+    }
+}
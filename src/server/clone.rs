@@ -102,7 +102,7 @@ impl UploadPartInputMultiplier {
             UploadPartInput::builder()
                 .body(body)
                 .set_bucket(self.bucket.clone())
-                .set_content_length(self.content_length.clone())
+                .set_content_length(self.content_length)
                 .set_content_md5(self.content_md5.clone())
                 .set_checksum_algorithm(self.checksum_algorithm.clone())
                 .set_checksum_crc32(self.checksum_crc32.clone())
@@ -110,7 +110,7 @@ impl UploadPartInputMultiplier {
                 .set_checksum_sha1(self.checksum_sha1.clone())
                 .set_checksum_sha256(self.checksum_sha256.clone())
                 .set_key(self.key.clone())
-                .set_part_number(self.part_number.clone())
+                .set_part_number(self.part_number)
                 .set_upload_id(self.upload_id.clone())
                 .set_sse_customer_algorithm(self.sse_customer_algorithm.clone())
                 .set_sse_customer_key(self.sse_customer_key.clone())
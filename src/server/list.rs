@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_sdk_s3::types::{CommonPrefix, Object};
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::ServiceError;
+use futures::StreamExt;
+use itertools::{Either, Itertools};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+use super::remote::{RemoteMessage, S3Remote};
+
+pub struct MergedList {
+    pub contents: Vec<Object>,
+    pub common_prefixes: Vec<CommonPrefix>,
+    pub is_truncated: bool,
+    /// Where the next page starts, if truncated.
+    pub next_start_after: Option<String>,
+}
+
+enum ListEntry {
+    Object(Object),
+    Prefix(CommonPrefix),
+}
+
+/// Lists a page from every remote concurrently and merges them by key.
+/// A key listed by several remotes is taken from the first of `remotes`.
+/// Remotes failing to list are left out; only when all of them fail is the first error returned.
+pub async fn list_merged(
+    remotes: Vec<&S3Remote>,
+    prefix: Option<String>,
+    delimiter: Option<String>,
+    max_keys: i32,
+    start_after: Option<String>,
+) -> Option<Result<MergedList, ServiceError<ListObjectsV2Error, HttpResponse>>> {
+    let results = futures::stream::iter(remotes)
+        .map(|remote| {
+            let prefix = prefix.clone();
+            let delimiter = delimiter.clone();
+            let start_after = start_after.clone();
+            async move {
+                let Some(result) = (try {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(RemoteMessage::ListObjects {
+                            prefix,
+                            delimiter,
+                            max_keys: Some(max_keys),
+                            start_after,
+                            reply: tx,
+                        })
+                        .await
+                        .ok()?;
+                    rx.await.ok()??
+                }) else {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    return None;
+                };
+                Some((remote, result))
+            }
+        })
+        .boxed()
+        .buffered(8)
+        .filter_map(|e| async { e })
+        .collect::<Vec<_>>()
+        .await;
+
+    let (pages, failures): (Vec<_>, Vec<_>) =
+        results
+            .into_iter()
+            .partition_map(|(remote, result)| match result {
+                Ok(output) => Either::Left(output),
+                Err(e) => Either::Right((remote, e)),
+            });
+
+    if pages.is_empty() {
+        return failures.into_iter().next().map(|(_, e)| Err(e));
+    }
+    for (remote, e) in failures {
+        warn!(
+            "remote({:?}) failed to list. merging without it: {:?}",
+            remote.name, e
+        );
+    }
+
+    // Each remote page holds its first `max_keys` entries, so the first `max_keys` merged entries are complete.
+    let mut entries = BTreeMap::new();
+    let mut is_truncated = false;
+    for page in pages {
+        is_truncated |= page.is_truncated().unwrap_or(false);
+        for object in page.contents() {
+            if let Some(key) = object.key() {
+                entries
+                    .entry(key.to_owned())
+                    .or_insert_with(|| ListEntry::Object(object.clone()));
+            }
+        }
+        for common_prefix in page.common_prefixes() {
+            if let Some(prefix) = common_prefix.prefix() {
+                entries
+                    .entry(prefix.to_owned())
+                    .or_insert_with(|| ListEntry::Prefix(common_prefix.clone()));
+            }
+        }
+    }
+
+    let max_keys = usize::try_from(max_keys).unwrap_or(0);
+    is_truncated |= entries.len() > max_keys;
+
+    let mut list = MergedList {
+        contents: vec![],
+        common_prefixes: vec![],
+        is_truncated,
+        next_start_after: None,
+    };
+    let mut last = None;
+    for (key, entry) in entries.into_iter().take(max_keys) {
+        last = Some(match entry {
+            ListEntry::Object(object) => {
+                list.contents.push(object);
+                key
+            }
+            ListEntry::Prefix(common_prefix) => {
+                list.common_prefixes.push(common_prefix);
+                // Skip every key under the prefix by starting after it followed by the highest code point.
+                format!("{}\u{10FFFF}", key)
+            }
+        });
+    }
+    if is_truncated {
+        list.next_start_after = last;
+    }
+
+    Some(Ok(list))
+}
@@ -1,13 +1,34 @@
+pub mod auth;
+pub mod bucket;
 pub mod clone;
+pub mod list;
+pub mod policy;
+pub mod presign;
+pub mod read;
 pub mod remote;
+pub mod replicate;
 pub mod stream;
-use crate::db::{ListObjectTokens, MultipartUploadIds, PartUploadStatus, RemoteMultipartUploadId};
+pub mod token;
+use crate::db::{
+    BucketPolicyDocument, ListObjectTokens, MultipartUploadIds, PartUploadStatus,
+    RemoteMultipartUploadId, RepairOperation, RepairTask,
+};
 use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::operation::copy_object::CopyObjectOutput as AwsCopyObjectOutput;
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::get_object_tagging::GetObjectTaggingError;
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::operation::list_objects::ListObjectsOutput as AwsListObjectsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output as AwsListObjectsV2Output;
 use aws_sdk_s3::operation::RequestId;
+use aws_sdk_s3::types::{CommonPrefix, CopyObjectResult, MultipartUpload};
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 use aws_smithy_runtime_api::client::result::ServiceError;
 use futures::StreamExt;
@@ -15,27 +36,51 @@ use itertools::{Either, Itertools};
 use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
 use s3s::dto::{
-    Bucket, CompleteMultipartUploadInput, CompleteMultipartUploadOutput,
-    CreateMultipartUploadInput, CreateMultipartUploadOutput, DeleteObjectInput, DeleteObjectOutput,
-    DeleteObjectsInput, DeleteObjectsOutput, GetBucketLocationInput, GetBucketLocationOutput,
-    GetObjectInput, GetObjectOutput, HeadBucketInput, HeadBucketOutput, HeadObjectInput,
-    HeadObjectOutput, ListBucketsInput, ListBucketsOutput, ListObjectsV2Input, ListObjectsV2Output,
-    PutObjectInput, PutObjectOutput, UploadPartInput, UploadPartOutput,
+    AbortMultipartUploadInput, AbortMultipartUploadOutput, Bucket, CompleteMultipartUploadInput,
+    CompleteMultipartUploadOutput, CopyObjectInput, CopyObjectOutput, CopySource,
+    CreateMultipartUploadInput, CreateMultipartUploadOutput, DeleteBucketPolicyInput,
+    DeleteBucketPolicyOutput, DeleteObjectInput, DeleteObjectOutput, DeleteObjectTaggingInput,
+    DeleteObjectTaggingOutput, DeleteObjectsInput, DeleteObjectsOutput, GetBucketLocationInput,
+    GetBucketLocationOutput, GetBucketPolicyInput, GetBucketPolicyOutput, GetObjectInput,
+    GetObjectOutput, GetObjectTaggingInput, GetObjectTaggingOutput, HeadBucketInput,
+    HeadBucketOutput, HeadObjectInput, HeadObjectOutput, ListBucketsInput, ListBucketsOutput,
+    ListMultipartUploadsInput, ListMultipartUploadsOutput, ListObjectVersionsInput,
+    ListObjectVersionsOutput, ListObjectsInput, ListObjectsOutput, ListObjectsV2Input,
+    ListObjectsV2Output, ListPartsInput, ListPartsOutput, PutBucketPolicyInput,
+    PutBucketPolicyOutput, PutObjectInput, PutObjectOutput, PutObjectTaggingInput,
+    PutObjectTaggingOutput, UploadPartCopyInput, UploadPartCopyOutput, UploadPartInput,
+    UploadPartOutput,
 };
 use s3s::{s3_error, S3Error, S3ErrorCode, S3Request, S3Response, S3Result, S3};
 use s3s_aws::conv::AwsConversion;
 use tokio::sync::oneshot;
-use tracing::{error, info, instrument, warn};
+use tracing::{error, info, instrument, warn, Instrument};
 
+use crate::config::s3_target::{ListMode, RoutingRule};
 use crate::db::MongoDB;
 
+use self::auth::{check_presigned_expires, ReproxyAuth};
+use self::bucket::VirtualBucket;
 use self::clone::{PutObjectInputMultiplier, UploadPartInputMultiplier};
+use self::list::list_merged;
+use self::policy::{Action, Decision, PolicyDocument, PolicyRequest};
+use self::read::{read_object, read_order, ReadResult};
 use self::remote::S3Remote;
+use self::replicate::{replicate_object, upload_part_from_remote};
+use self::stream::failover_bytestream;
+use self::token::ListTokenSigner;
+
+/// Address of the client, attached to each request by the connection it arrives on.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
 
 pub struct S3Reproxy {
-    pub bucket: String,
+    pub buckets: Vec<VirtualBucket>,
+    /// Remotes of every bucket.
     pub remotes: Arc<Vec<S3Remote>>,
     pub db: Arc<MongoDB>,
+    pub list_tokens: Option<ListTokenSigner>,
+    pub auth: ReproxyAuth,
 }
 
 #[inline(always)]
@@ -63,14 +108,25 @@ impl S3 for S3Reproxy {
     #[instrument(skip_all)]
     async fn list_buckets(
         &self,
-        _req: S3Request<ListBucketsInput>,
+        req: S3Request<ListBucketsInput>,
     ) -> S3Result<S3Response<ListBucketsOutput>> {
-        info!("(intercepted) {}", self.bucket);
+        self.authorize(&req, None, Action::ListAllMyBuckets, None)
+            .await?;
+
+        info!(
+            "(intercepted) {}",
+            self.buckets.iter().map(|b| &b.name).join(", ")
+        );
         Ok(S3Response::new(ListBucketsOutput {
-            buckets: Some(vec![Bucket {
-                creation_date: None,
-                name: Some(self.bucket.clone()),
-            }]),
+            buckets: Some(
+                self.buckets
+                    .iter()
+                    .map(|b| Bucket {
+                        creation_date: None,
+                        name: Some(b.name.clone()),
+                    })
+                    .collect(),
+            ),
             owner: None,
         }))
     }
@@ -80,10 +136,9 @@ impl S3 for S3Reproxy {
         &self,
         req: S3Request<GetBucketLocationInput>,
     ) -> S3Result<S3Response<GetBucketLocationOutput>> {
-        if req.input.bucket != self.bucket {
-            warn!("(intercepted) not found");
-            return Err(s3_error!(NoSuchBucket));
-        }
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(&req, Some(bucket), Action::GetBucketLocation, None)
+            .await?;
 
         let output = GetBucketLocationOutput::default();
         info!("(intercepted) ok");
@@ -95,25 +150,130 @@ impl S3 for S3Reproxy {
         &self,
         req: S3Request<HeadBucketInput>,
     ) -> S3Result<S3Response<HeadBucketOutput>> {
-        if req.input.bucket != self.bucket {
-            warn!("(intercepted) not found");
-            return Err(s3_error!(NoSuchBucket));
-        }
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(&req, Some(bucket), Action::ListBucket, None)
+            .await?;
 
         let output = HeadBucketOutput::default();
         info!("(intercepted) ok");
         Ok(S3Response::new(output))
     }
 
+    #[instrument(skip_all, fields(bucket = req.input.bucket))]
+    async fn get_bucket_policy(
+        &self,
+        req: S3Request<GetBucketPolicyInput>,
+    ) -> S3Result<S3Response<GetBucketPolicyOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(&req, Some(bucket), Action::GetBucketPolicy, None)
+            .await?;
+
+        let document = self
+            .db
+            .bucket_policies
+            .find_one(doc! { "bucket": &bucket.name })
+            .await
+            .map_err(|e| {
+                error!("Failed to find bucket policy: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+        let Some(document) = document else {
+            info!("(intercepted) no policy");
+            return Err(s3_error!(NoSuchBucketPolicy));
+        };
+
+        info!("(intercepted) ok");
+        Ok(S3Response::new(GetBucketPolicyOutput {
+            policy: Some(document.policy),
+        }))
+    }
+
+    /// The policy is stored in MongoDB and enforced by the proxy, so it is never put on the remotes.
+    #[instrument(skip_all, fields(bucket = req.input.bucket))]
+    async fn put_bucket_policy(
+        &self,
+        req: S3Request<PutBucketPolicyInput>,
+    ) -> S3Result<S3Response<PutBucketPolicyOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(&req, Some(bucket), Action::PutBucketPolicy, None)
+            .await?;
+
+        let policy = PolicyDocument::parse(&req.input.policy, &bucket.name).map_err(|e| {
+            warn!("(intercepted) malformed policy: {}", e);
+            S3Error::with_message(S3ErrorCode::Custom("MalformedPolicy".into()), e.to_string())
+        })?;
+
+        self.db
+            .bucket_policies
+            .replace_one(
+                doc! { "bucket": &bucket.name },
+                BucketPolicyDocument {
+                    id: None,
+                    bucket: bucket.name.clone(),
+                    policy: req.input.policy,
+                    updated_at: mongodb::bson::DateTime::now(),
+                },
+            )
+            .upsert(true)
+            .await
+            .map_err(|e| {
+                error!("Failed to save bucket policy: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+        bucket.cache_policy(Some(Arc::new(policy)));
+
+        info!("(intercepted) ok");
+        Ok(S3Response::new(PutBucketPolicyOutput::default()))
+    }
+
+    #[instrument(skip_all, fields(bucket = req.input.bucket))]
+    async fn delete_bucket_policy(
+        &self,
+        req: S3Request<DeleteBucketPolicyInput>,
+    ) -> S3Result<S3Response<DeleteBucketPolicyOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(&req, Some(bucket), Action::DeleteBucketPolicy, None)
+            .await?;
+
+        self.db
+            .bucket_policies
+            .delete_one(doc! { "bucket": &bucket.name })
+            .await
+            .map_err(|e| {
+                error!("Failed to delete bucket policy: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+        bucket.cache_policy(None);
+
+        info!("(intercepted) ok");
+        Ok(S3Response::new(DeleteBucketPolicyOutput::default()))
+    }
+
     #[instrument(skip_all, name = "s3s/upload_part", fields(part_number = &req.input.part_number))]
     async fn upload_part(
         &self,
         req: S3Request<UploadPartInput>,
     ) -> S3Result<S3Response<UploadPartOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
         info!("multipling...");
-        let (id, remotes) = self.initiate_multipart(req.input.upload_id.clone()).await?;
+        let (id, remotes) = self
+            .initiate_multipart(bucket, req.input.upload_id.clone())
+            .await?;
 
         let input = UploadPartInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::UploadPart,
+            input.key.clone(),
+            Some(id),
+            remotes.iter().filter_map(|(remote, _)| *remote),
+        );
 
         let (mut input_multiplier, signal) = UploadPartInputMultiplier::from_input(input);
         let remotes = futures::stream::iter(remotes.into_iter())
@@ -176,7 +336,9 @@ impl S3 for S3Reproxy {
 
         let results = results.into_iter().flatten().collect::<Vec<_>>();
 
-        let output = output_remote_inconsistent(results)?;
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
 
         self.db
             .multipart_upload_ids
@@ -199,28 +361,57 @@ impl S3 for S3Reproxy {
         Ok(S3Response::new(UploadPartOutput::try_from_aws(output)?))
     }
 
-    #[instrument(skip_all, name = "s3s/complete_multipart_upload")]
-    async fn complete_multipart_upload(
+    #[instrument(skip_all, name = "s3s/upload_part_copy", fields(part_number = &req.input.part_number))]
+    async fn upload_part_copy(
         &self,
-        req: S3Request<CompleteMultipartUploadInput>,
-    ) -> S3Result<S3Response<CompleteMultipartUploadOutput>> {
-        let (id, remotes) = self.initiate_multipart(req.input.upload_id.clone()).await?;
-
-        let input = CompleteMultipartUploadInput::try_into_aws(req.input)?;
+        req: S3Request<UploadPartCopyInput>,
+    ) -> S3Result<S3Response<UploadPartCopyOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let (source_key, source_version_id) =
+            self.parse_copy_source(bucket, &req.input.copy_source)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::GetObject,
+            Some(source_key.as_str()),
+        )
+        .await?;
+        let (id, remotes) = self
+            .initiate_multipart(bucket, req.input.upload_id.clone())
+            .await?;
+
+        let input = UploadPartCopyInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::UploadPartCopy,
+            input.key.clone(),
+            Some(id),
+            remotes.iter().filter_map(|(remote, _)| *remote),
+        );
 
-        let results = futures::stream::iter(remotes.into_iter())
+        let (ids, results) = futures::stream::iter(remotes.into_iter())
             .map(|(remote, upload)| {
                 let value = input.clone();
+                let source_key = source_key.clone();
+                let source_version_id = source_version_id.clone();
                 async move {
                     if let Some(remote) = remote {
-                        let Some(_result) = (try {
+                        let Some(result) = (try {
                             let (tx, rx) = oneshot::channel();
-                            let mut input = value.clone();
+                            let mut input = value;
                             input.upload_id = Some(upload.upload_id.clone());
                             remote
                                 .tx
-                                .send(remote::RemoteMessage::CompleteMultiPartUpload {
+                                .send(remote::RemoteMessage::UploadPartCopy {
                                     input,
+                                    source_key,
+                                    source_version_id,
                                     reply: tx,
                                 })
                                 .await
@@ -228,30 +419,177 @@ impl S3 for S3Reproxy {
                             rx.await.ok()??
                         }) else {
                             warn!("remote({:?}) request failed. cancelling", remote.name);
-                            return upload.cancelled();
+                            return (upload.cancelled(), None);
                         };
-                        upload
+                        let upload_id = upload.upload_id.clone();
+                        (upload, Some((remote, upload_id, result)))
                     } else {
                         info!(
                             "remote({:?}) has already been cancelled by another s3-reproxy replica",
                             upload.remote_name
                         );
-                        upload
+                        (upload, None)
                     }
                 }
             })
             .boxed()
             .buffer_unordered(8)
+            .collect::<(Vec<_>, Vec<_>)>()
+            .await;
+
+        // Remotes which are missing the source range receive it from a remote that succeeded, as a plain UploadPart.
+        let source = results
+            .iter()
+            .flatten()
+            .find(|(_, _, result)| result.is_ok())
+            .map(|(remote, _, _)| *remote);
+        let (missing, mut results): (Vec<_>, Vec<_>) = results.into_iter().flatten().partition_map(
+            |(remote, upload_id, result)| match result {
+                Err(e)
+                    if source.is_some()
+                        && matches!(e.err().meta().code(), Some("NoSuchKey" | "InvalidRange")) =>
+                {
+                    Either::Left((remote, upload_id, e))
+                }
+                result => Either::Right((remote.name.clone(), result)),
+            },
+        );
+
+        if let Some(source) = source.filter(|_| !missing.is_empty()) {
+            results.extend(
+                upload_part_from_remote(source, &input, source_key, source_version_id, missing)
+                    .await,
+            );
+        }
+
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
+
+        self.db
+            .multipart_upload_ids
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": {
+                        "upload_ids": mongodb::bson::to_bson(&ids).unwrap(),
+                    },
+                },
+            )
+            .await
+            .map_err(|e| {
+                error!("mongodb error: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+
+        info!("ok (upload_id: {})", id);
+
+        Ok(S3Response::new(UploadPartCopyOutput::try_from_aws(output)?))
+    }
+
+    #[instrument(skip_all, name = "s3s/complete_multipart_upload")]
+    async fn complete_multipart_upload(
+        &self,
+        req: S3Request<CompleteMultipartUploadInput>,
+    ) -> S3Result<S3Response<CompleteMultipartUploadOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let (id, remotes) = self
+            .initiate_multipart(bucket, req.input.upload_id.clone())
+            .await?;
+
+        let input = CompleteMultipartUploadInput::try_into_aws(req.input)?;
+
+        let results = futures::stream::iter(remotes.into_iter())
+            .map(|(remote, upload)| {
+                let value = input.clone();
+                async move {
+                    let Some(remote) = remote else {
+                        info!(
+                            "remote({:?}) has already been cancelled by another s3-reproxy replica",
+                            upload.remote_name
+                        );
+                        return (upload, None);
+                    };
+                    let Some(result) = (try {
+                        let (tx, rx) = oneshot::channel();
+                        let mut input = value;
+                        input.upload_id = Some(upload.upload_id.clone());
+                        remote
+                            .tx
+                            .send(remote::RemoteMessage::CompleteMultiPartUpload {
+                                input,
+                                reply: tx,
+                            })
+                            .await
+                            .ok()?;
+                        rx.await.ok()??
+                    }) else {
+                        warn!("remote({:?}) request failed. cancelling", remote.name);
+                        return (upload.cancelled(), None);
+                    };
+                    (upload, Some((remote.name.clone(), result)))
+                }
+            })
+            .boxed()
+            .buffer_unordered(8)
             .collect::<Vec<_>>()
             .await;
 
-        let bson = mongodb::bson::to_bson(&results).map_err(|e| {
+        let scope = RepairScope {
+            operation: RepairOperation::CompleteMultipartUpload,
+            keys: input.key.clone().into_iter().collect(),
+            upload_id: Some(id),
+            remotes: results
+                .iter()
+                .map(|(upload, _)| upload.remote_name.clone())
+                .collect(),
+        };
+        let (uploads, results): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+        let failed = results
+            .iter()
+            .flatten()
+            .filter(|(_, result)| result.is_err())
+            .map(|(remote, _)| remote.clone())
+            .collect_vec();
+
+        let output = self
+            .output_remote_inconsistent(
+                bucket,
+                Some(scope),
+                results.into_iter().flatten().collect(),
+            )
+            .await;
+
+        // Once completed, remote uploads which failed are aborted by the garbage collector and the object is repaired instead.
+        // Otherwise they are kept open for the client to retry.
+        let uploads = match output {
+            Ok(_) => uploads
+                .into_iter()
+                .map(|u| {
+                    if failed.contains(&u.remote_name) {
+                        u.cancelled()
+                    } else {
+                        u
+                    }
+                })
+                .collect_vec(),
+            Err(_) => uploads,
+        };
+
+        let bson = mongodb::bson::to_bson(&uploads).map_err(|e| {
             error!("mongodb serialization error: {:?}", e);
             S3Error::new(S3ErrorCode::InternalError)
         })?;
 
-        let (set, result) = if results.iter().all(|e| e.status == PartUploadStatus::Open) {
-            (
+        let (set, result) = match output {
+            Ok(_) => (
                 doc! {
                     "$set": {
                         "upload_ids": bson,
@@ -263,17 +601,15 @@ impl S3 for S3Reproxy {
                     key: input.key,
                     ..Default::default()
                 })),
-            )
-        } else {
-            warn!("no remotes are remains without rejection in multipart upload.");
-            (
+            ),
+            Err(e) => (
                 doc! {
                     "$set": {
                         "upload_ids": bson,
                     },
                 },
-                Err(S3Error::new(S3ErrorCode::InternalError)),
-            )
+                Err(e),
+            ),
         };
 
         self.db
@@ -290,140 +626,932 @@ impl S3 for S3Reproxy {
         result
     }
 
-    #[instrument(skip_all, name = "s3s/create_multipart_upload")]
-    async fn create_multipart_upload(
+    #[instrument(skip_all, name = "s3s/abort_multipart_upload")]
+    async fn abort_multipart_upload(
         &self,
-        req: S3Request<CreateMultipartUploadInput>,
-    ) -> S3Result<S3Response<CreateMultipartUploadOutput>> {
-        let input = CreateMultipartUploadInput::try_into_aws(req.input)?;
-        let results = futures::stream::iter(self.remotes.iter())
-            .map(|remote| async {
-                let Some(result) = (try {
-                    let (tx, rx) = oneshot::channel();
-                    remote
-                        .tx
-                        .send(remote::RemoteMessage::CreateMultiPartUpload {
-                            input: input.clone(),
-                            reply: tx,
-                        })
-                        .await
-                        .ok()?;
-                    rx.await.ok()??
-                }) else {
-                    warn!("remote({:?}) request failed. skipping", remote.name);
-                    return None;
-                };
-                Some((remote.name.clone(), result))
+        req: S3Request<AbortMultipartUploadInput>,
+    ) -> S3Result<S3Response<AbortMultipartUploadOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::AbortMultipartUpload,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let (id, remotes) = self
+            .initiate_multipart(bucket, req.input.upload_id.clone())
+            .await?;
+
+        let input = AbortMultipartUploadInput::try_into_aws(req.input)?;
+
+        let results = futures::stream::iter(remotes.into_iter())
+            .map(|(remote, upload)| {
+                let value = input.clone();
+                async move {
+                    let Some(remote) = remote else {
+                        info!(
+                            "remote({:?}) has already been cancelled by another s3-reproxy replica",
+                            upload.remote_name
+                        );
+                        return upload;
+                    };
+                    let Some(result) = (try {
+                        let (tx, rx) = oneshot::channel();
+                        let mut input = value;
+                        input.upload_id = Some(upload.upload_id.clone());
+                        remote
+                            .tx
+                            .send(remote::RemoteMessage::AbortMultiPartUpload { input, reply: tx })
+                            .await
+                            .ok()?;
+                        rx.await.ok()??
+                    }) else {
+                        warn!("remote({:?}) request failed. cancelling", remote.name);
+                        return upload.cancelled();
+                    };
+                    match result {
+                        Ok(_) => upload.aborted(),
+                        Err(e) if e.err().is_no_such_upload() => {
+                            info!(
+                                "remote({:?}) has no such upload. treating as aborted",
+                                remote.name
+                            );
+                            upload.aborted()
+                        }
+                        Err(e) => {
+                            warn!("remote({:?}) failed: {:?}. cancelling", remote.name, e);
+                            upload.cancelled()
+                        }
+                    }
+                }
             })
             .boxed()
             .buffer_unordered(8)
-            .filter_map(|e| async { e })
             .collect::<Vec<_>>()
             .await;
 
-        let ids = results
-            .into_iter()
-            .filter_map(|(remote, result)| match result {
-                Ok(output) => Some(RemoteMultipartUploadId {
-                    remote_name: remote,
-                    upload_id: output.upload_id.expect("upload_id missing"),
-                    status: PartUploadStatus::Open,
-                }),
-                Err(e) => {
-                    warn!("remote({:?}) failed: {:?}", remote, e);
-                    None
-                }
-            });
+        // The upload is aborted for the client either way; cancelled remote uploads are retried by the garbage collector.
+        if results
+            .iter()
+            .any(|u| u.status == PartUploadStatus::Cancelled)
+        {
+            warn!("some remote uploads were not aborted. leaving them to the garbage collector.");
+        }
 
-        let ids = MultipartUploadIds {
-            upload_ids: ids.collect(),
-            created_at: mongodb::bson::DateTime::now(),
-            completed_at: None,
-            aborted_at: None,
-        };
+        let bson = mongodb::bson::to_bson(&results).map_err(|e| {
+            error!("mongodb serialization error: {:?}", e);
+            S3Error::new(S3ErrorCode::InternalError)
+        })?;
 
-        let id = self
-            .db
+        self.db
             .multipart_upload_ids
-            .insert_one(ids)
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": {
+                        "upload_ids": bson,
+                        "aborted_at": mongodb::bson::DateTime::now(),
+                    },
+                },
+            )
             .await
             .map_err(|e| {
                 error!("mongodb error: {:?}", e);
                 S3Error::new(S3ErrorCode::InternalError)
-            })?
-            .inserted_id
-            .as_object_id()
-            .unwrap()
-            .to_hex();
+            })?;
 
         info!("ok (upload_id: {})", id);
 
-        Ok(S3Response::new(CreateMultipartUploadOutput {
-            bucket: input.bucket,
-            key: input.key,
-            upload_id: Some(id),
-            ..Default::default()
-        }))
+        Ok(S3Response::new(AbortMultipartUploadOutput::default()))
     }
 
-    #[instrument(skip_all, name = "s3s/put_object")]
-    async fn put_object(
+    #[instrument(skip_all, name = "s3s/list_parts")]
+    async fn list_parts(
         &self,
-        req: S3Request<PutObjectInput>,
-    ) -> S3Result<S3Response<PutObjectOutput>> {
-        let input = PutObjectInput::try_into_aws(req.input)?;
-        let (mut input_multiplier, signal) = PutObjectInputMultiplier::from_input(input);
-        let remotes = futures::stream::iter(self.remotes.iter())
-            .map(|remote| {
-                let input = input_multiplier.input();
-                async move { (remote, input.await.unwrap()) }
+        req: S3Request<ListPartsInput>,
+    ) -> S3Result<S3Response<ListPartsOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::ListMultipartUploadParts,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let (id, remotes) = self
+            .initiate_multipart(bucket, req.input.upload_id.clone())
+            .await?;
+
+        let order = self.read_remotes(bucket, None);
+        let read_remotes = remotes
+            .into_iter()
+            .filter_map(|(remote, upload)| {
+                let remote = remote?;
+                let position = order.iter().position(|r| r.name == remote.name)?;
+                Some((position, remote, upload))
             })
-            .boxed()
-            .buffer_unordered(8)
-            .collect::<Vec<_>>()
-            .await;
-        input_multiplier.close();
-        signal.await.unwrap();
-        let results = futures::stream::iter(remotes.into_iter())
-            .map(|(remote, input)| async move {
-                let Some(result) = (try {
+            .sorted_by_key(|(position, _, _)| *position)
+            .map(|(_, remote, upload)| (remote, upload));
+
+        let input = ListPartsInput::try_into_aws(req.input)?;
+
+        let Some((result, remote)) = ('request: {
+            for (remote, upload) in read_remotes {
+                let Some(output) = (try {
                     let (tx, rx) = oneshot::channel();
+                    let mut input = input.clone();
+                    input.upload_id = Some(upload.upload_id.clone());
                     remote
                         .tx
-                        .send(remote::RemoteMessage::PutObject { input, reply: tx })
+                        .send(remote::RemoteMessage::ListParts { input, reply: tx })
                         .await
                         .ok()?;
                     rx.await.ok()??
                 }) else {
                     warn!("remote({:?}) request failed. skipping", remote.name);
-                    return None;
+                    continue;
                 };
-                Some((remote.name.clone(), result))
-            })
-            .boxed()
+                break 'request Some((output, remote.name.clone()));
+            }
+            None
+        }) else {
+            warn!("no remotes available!");
+            return Err(s3_error!(InternalError));
+        };
+
+        info!("ok (remote: {}, upload_id: {})", remote, id);
+
+        let mut output = result.map_err(convert_sdk_err)?;
+        output.upload_id = Some(id.to_hex());
+
+        Ok(S3Response::new(ListPartsOutput::try_from_aws(output)?))
+    }
+
+    #[instrument(skip_all, name = "s3s/list_multipart_uploads")]
+    async fn list_multipart_uploads(
+        &self,
+        req: S3Request<ListMultipartUploadsInput>,
+    ) -> S3Result<S3Response<ListMultipartUploadsOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::ListBucketMultipartUploads,
+            Some(req.input.prefix.as_deref().unwrap_or_default()),
+        )
+        .await?;
+        let input = ListMultipartUploadsInput::try_into_aws(req.input)?;
+
+        let prefix = input.prefix.clone().unwrap_or_default();
+        let max_uploads = input.max_uploads.unwrap_or(1000).clamp(1, 1000) as usize;
+
+        let buckets = if self.upload_in_bucket(bucket, None) {
+            vec![Some(bucket.name.clone()), None]
+        } else {
+            vec![Some(bucket.name.clone())]
+        };
+        let mut filter = doc! {
+            "bucket": { "$in": buckets },
+            "key": { "$gte": &prefix },
+            "completed_at": None::<mongodb::bson::DateTime>,
+            "aborted_at": None::<mongodb::bson::DateTime>,
+        };
+
+        if let Some(key_marker) = &input.key_marker {
+            let upload_id_marker = input
+                .upload_id_marker
+                .as_deref()
+                .map(ObjectId::parse_str)
+                .transpose()
+                .map_err(|e| {
+                    warn!("(intercepted) invalid upload_id_marker: {:?}", e);
+                    S3Error::new(S3ErrorCode::InvalidArgument)
+                })?;
+            filter.insert(
+                "$or",
+                match upload_id_marker {
+                    Some(upload_id) => vec![
+                        doc! { "key": { "$gt": key_marker } },
+                        doc! { "key": key_marker, "_id": { "$gt": upload_id } },
+                    ],
+                    None => vec![doc! { "key": { "$gt": key_marker } }],
+                },
+            );
+        }
+
+        // A key_marker ending with the delimiter is a CommonPrefix returned by the previous page.
+        let skip_prefix = input
+            .key_marker
+            .as_deref()
+            .zip(input.delimiter.as_deref())
+            .filter(|(marker, delimiter)| marker.ends_with(delimiter))
+            .map(|(marker, _)| marker);
+
+        let mut cursor = self
+            .db
+            .multipart_upload_ids
+            .find(filter)
+            .sort(doc! { "key": 1, "_id": 1 })
+            .await
+            .map_err(|e| {
+                error!("mongodb error: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+
+        let mut uploads = vec![];
+        let mut common_prefixes: Vec<String> = vec![];
+        let mut last = None;
+        let mut is_truncated = false;
+
+        while let Some(upload) = cursor.next().await {
+            let upload = upload.map_err(|e| {
+                error!("mongodb error: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+            let (Some(id), Some(key)) = (upload.id, upload.key) else {
+                continue;
+            };
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            if skip_prefix.is_some_and(|p| key.starts_with(p)) {
+                continue;
+            }
+
+            let common_prefix = input.delimiter.as_deref().and_then(|d| {
+                key[prefix.len()..]
+                    .find(d)
+                    .map(|i| key[..prefix.len() + i + d.len()].to_string())
+            });
+            if common_prefix.is_some() && common_prefixes.last() == common_prefix.as_ref() {
+                continue;
+            }
+
+            if uploads.len() + common_prefixes.len() >= max_uploads {
+                is_truncated = true;
+                break;
+            }
+
+            match common_prefix {
+                Some(common_prefix) => {
+                    last = Some((common_prefix.clone(), None));
+                    common_prefixes.push(common_prefix);
+                }
+                None => {
+                    last = Some((key.clone(), Some(id.to_hex())));
+                    uploads.push(
+                        MultipartUpload::builder()
+                            .key(key)
+                            .upload_id(id.to_hex())
+                            .initiated(aws_smithy_types::DateTime::from_millis(
+                                upload.created_at.timestamp_millis(),
+                            ))
+                            .build(),
+                    );
+                }
+            }
+        }
+
+        let (next_key_marker, next_upload_id_marker) = match last {
+            Some((key, upload_id)) if is_truncated => (Some(key), upload_id),
+            _ => (None, None),
+        };
+
+        info!(
+            "(intercepted) ok ({} uploads, {} common prefixes)",
+            uploads.len(),
+            common_prefixes.len()
+        );
+
+        let output =
+            aws_sdk_s3::operation::list_multipart_uploads::ListMultipartUploadsOutput::builder()
+                .set_bucket(input.bucket)
+                .set_key_marker(input.key_marker)
+                .set_upload_id_marker(input.upload_id_marker)
+                .set_next_key_marker(next_key_marker)
+                .set_next_upload_id_marker(next_upload_id_marker)
+                .set_prefix(input.prefix)
+                .set_delimiter(input.delimiter)
+                .max_uploads(max_uploads as i32)
+                .is_truncated(is_truncated)
+                .set_uploads(Some(uploads))
+                .set_common_prefixes(Some(
+                    common_prefixes
+                        .into_iter()
+                        .map(|p| CommonPrefix::builder().prefix(p).build())
+                        .collect(),
+                ))
+                .build();
+
+        Ok(S3Response::new(ListMultipartUploadsOutput::try_from_aws(
+            output,
+        )?))
+    }
+
+    #[instrument(skip_all, name = "s3s/create_multipart_upload")]
+    async fn create_multipart_upload(
+        &self,
+        req: S3Request<CreateMultipartUploadInput>,
+    ) -> S3Result<S3Response<CreateMultipartUploadOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let input = CreateMultipartUploadInput::try_into_aws(req.input)?;
+        let results = futures::stream::iter(self.write_remotes(bucket, rule))
+            .map(|remote| async {
+                let Some(result) = (try {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::CreateMultiPartUpload {
+                            input: input.clone(),
+                            reply: tx,
+                        })
+                        .await
+                        .ok()?;
+                    rx.await.ok()??
+                }) else {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    return None;
+                };
+                Some((remote.name.clone(), result))
+            })
+            .boxed()
             .buffer_unordered(8)
             .filter_map(|e| async { e })
             .collect::<Vec<_>>()
             .await;
 
-        let output = output_remote_inconsistent(results)?;
+        let ids = results
+            .into_iter()
+            .filter_map(|(remote, result)| match result {
+                Ok(output) => Some(RemoteMultipartUploadId {
+                    remote_name: remote,
+                    upload_id: output.upload_id.expect("upload_id missing"),
+                    status: PartUploadStatus::Open,
+                }),
+                Err(e) => {
+                    warn!("remote({:?}) failed: {:?}", remote, e);
+                    None
+                }
+            });
+
+        let ids = MultipartUploadIds {
+            id: None,
+            bucket: Some(bucket.name.clone()),
+            key: input.key.clone(),
+            upload_ids: ids.collect(),
+            created_at: mongodb::bson::DateTime::now(),
+            completed_at: None,
+            aborted_at: None,
+        };
+
+        let id = self
+            .db
+            .multipart_upload_ids
+            .insert_one(ids)
+            .await
+            .map_err(|e| {
+                error!("mongodb error: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?
+            .inserted_id
+            .as_object_id()
+            .unwrap()
+            .to_hex();
+
+        info!("ok (upload_id: {})", id);
+
+        Ok(S3Response::new(CreateMultipartUploadOutput {
+            bucket: input.bucket,
+            key: input.key,
+            upload_id: Some(id),
+            ..Default::default()
+        }))
+    }
+
+    #[instrument(skip_all, name = "s3s/put_object")]
+    async fn put_object(
+        &self,
+        req: S3Request<PutObjectInput>,
+    ) -> S3Result<S3Response<PutObjectOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let input = PutObjectInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::PutObject,
+            input.key.clone(),
+            None,
+            self.write_remotes(bucket, rule),
+        );
+        let (mut input_multiplier, signal) = PutObjectInputMultiplier::from_input(input);
+        let remotes = futures::stream::iter(self.write_remotes(bucket, rule))
+            .map(|remote| {
+                let input = input_multiplier.input();
+                async move { (remote, input.await.unwrap()) }
+            })
+            .boxed()
+            .buffer_unordered(8)
+            .collect::<Vec<_>>()
+            .await;
+        input_multiplier.close();
+        signal.await.unwrap();
+        let results = futures::stream::iter(remotes.into_iter())
+            .map(|(remote, input)| async move {
+                let Some(result) = (try {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::PutObject { input, reply: tx })
+                        .await
+                        .ok()?;
+                    rx.await.ok()??
+                }) else {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    return None;
+                };
+                Some((remote.name.clone(), result))
+            })
+            .boxed()
+            .buffer_unordered(8)
+            .filter_map(|e| async { e })
+            .collect::<Vec<_>>()
+            .await;
+
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
 
         Ok(S3Response::new(PutObjectOutput::try_from_aws(output)?))
     }
 
-    #[instrument(skip_all, name = "s3s/delete_objects")]
-    async fn delete_objects(
+    #[instrument(skip_all, name = "s3s/copy_object")]
+    async fn copy_object(
+        &self,
+        req: S3Request<CopyObjectInput>,
+    ) -> S3Result<S3Response<CopyObjectOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let (source_key, source_version_id) =
+            self.parse_copy_source(bucket, &req.input.copy_source)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::GetObject,
+            Some(source_key.as_str()),
+        )
+        .await?;
+
+        let input = CopyObjectInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::CopyObject,
+            input.key.clone(),
+            None,
+            self.write_remotes(bucket, rule),
+        );
+        let results = futures::stream::iter(self.write_remotes(bucket, rule))
+            .map(|remote| {
+                let input = input.clone();
+                let source_key = source_key.clone();
+                let source_version_id = source_version_id.clone();
+                async move {
+                    let Some(result) = (try {
+                        let (tx, rx) = oneshot::channel();
+                        remote
+                            .tx
+                            .send(remote::RemoteMessage::CopyObject {
+                                input,
+                                source_key,
+                                source_version_id,
+                                reply: tx,
+                            })
+                            .await
+                            .ok()?;
+                        rx.await.ok()??
+                    }) else {
+                        warn!("remote({:?}) request failed. skipping", remote.name);
+                        return None;
+                    };
+                    Some((remote, result))
+                }
+            })
+            .boxed()
+            .buffer_unordered(8)
+            .filter_map(|e| async { e })
+            .collect::<Vec<_>>()
+            .await;
+
+        // Remotes which are missing the source object receive the copied object from a remote that succeeded.
+        let source = results
+            .iter()
+            .find(|(_, result)| result.is_ok())
+            .map(|(remote, _)| *remote);
+        let key = input.key.as_deref().unwrap_or_default();
+        let results = futures::stream::iter(results.into_iter())
+            .map(|(remote, result)| async move {
+                let result = match (result, source) {
+                    (Err(e), Some(source)) if e.err().meta().code() == Some("NoSuchKey") => {
+                        warn!(
+                            "remote({:?}) does not have the source object. replicating from remote({:?})",
+                            remote.name, source.name
+                        );
+                        match replicate_object(source, remote, key).await {
+                            Ok(output) => Ok(AwsCopyObjectOutput::builder()
+                                .copy_object_result(
+                                    CopyObjectResult::builder().set_e_tag(output.e_tag).build(),
+                                )
+                                .build()),
+                            Err(re) => {
+                                error!("remote({:?}) replication failed: {}", remote.name, re);
+                                Err(e)
+                            }
+                        }
+                    }
+                    (result, _) => result,
+                };
+                (remote.name.clone(), result)
+            })
+            .boxed()
+            .buffer_unordered(4)
+            .collect::<Vec<_>>()
+            .await;
+
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
+
+        Ok(S3Response::new(CopyObjectOutput::try_from_aws(output)?))
+    }
+
+    #[instrument(skip_all, name = "s3s/delete_objects")]
+    async fn delete_objects(
+        &self,
+        req: S3Request<DeleteObjectsInput>,
+    ) -> S3Result<S3Response<DeleteObjectsOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::DeleteObject,
+            req.input.delete.objects.iter().map(|o| o.key.as_str()),
+        )
+        .await?;
+        let input = DeleteObjectsInput::try_into_aws(req.input)?;
+        let keys = input
+            .delete
+            .iter()
+            .flat_map(|d| d.objects())
+            .map(|o| o.key().to_owned())
+            .collect_vec();
+        // Every remote which receives writes to any of the keys.
+        let rules = keys.iter().map(|key| bucket.rule(key)).collect_vec();
+        let remotes = self
+            .bucket_remotes(bucket)
+            .into_iter()
+            .filter(|r| {
+                rules
+                    .iter()
+                    .any(|rule| rule.map_or(true, |rule| rule.writes_to(&r.name)))
+            })
+            .collect_vec();
+        let scope = RepairScope::new(
+            RepairOperation::DeleteObjects,
+            keys,
+            None,
+            remotes.iter().copied(),
+        );
+        let results = futures::stream::iter(remotes)
+            .map(|remote| async {
+                let Some(result) = (try {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::DeleteObjects {
+                            input: input.clone(),
+                            reply: tx,
+                        })
+                        .await
+                        .ok()?;
+                    rx.await.ok()??
+                }) else {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    return None;
+                };
+                Some((remote.name.clone(), result))
+            })
+            .boxed()
+            .buffer_unordered(4)
+            .filter_map(|e| async { e })
+            .collect::<Vec<_>>()
+            .await;
+
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
+
+        Ok(S3Response::new(DeleteObjectsOutput::try_from_aws(output)?))
+    }
+
+    #[instrument(skip_all, name = "s3s/delete_object")]
+    async fn delete_object(
+        &self,
+        req: S3Request<DeleteObjectInput>,
+    ) -> S3Result<S3Response<DeleteObjectOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::DeleteObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let input = DeleteObjectInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::DeleteObject,
+            input.key.clone(),
+            None,
+            self.write_remotes(bucket, rule),
+        );
+        let results = futures::stream::iter(self.write_remotes(bucket, rule))
+            .map(|remote| async {
+                let Some(result) = (try {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::DeleteObject {
+                            input: input.clone(),
+                            reply: tx,
+                        })
+                        .await
+                        .ok()?;
+                    rx.await.ok()??
+                }) else {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    return None;
+                };
+                Some((remote.name.clone(), result))
+            })
+            .boxed()
+            .buffer_unordered(4)
+            .filter_map(|e| async { e })
+            .collect::<Vec<_>>()
+            .await;
+
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
+
+        Ok(S3Response::new(DeleteObjectOutput::try_from_aws(output)?))
+    }
+
+    #[instrument(skip_all, name = "s3s/get_object")]
+    async fn get_object(
+        &self,
+        req: S3Request<GetObjectInput>,
+    ) -> S3Result<S3Response<GetObjectOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::GetObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let read_remotes = self.read_remotes(bucket, rule);
+
+        let input = GetObjectInput::try_into_aws(req.input)?;
+
+        let key = input.key.clone().unwrap_or_default();
+
+        let ReadResult { result, missing } = read_object(
+            read_remotes.clone(),
+            bucket.hedge.as_ref(),
+            |remote| {
+                let input = input.clone();
+                async move {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::GetObject { input, reply: tx })
+                        .await
+                        .ok()?;
+                    rx.await.ok()?
+                }
+            },
+            GetObjectError::is_no_such_key,
+        )
+        .await;
+
+        let Some((result, remote)) = result else {
+            warn!("no remotes available!");
+            return Err(s3_error!(InternalError));
+        };
+
+        info!("ok (remote: {})", remote.name);
+
+        // Remotes which may still have the object, in case the body fails midway.
+        let fallbacks = read_remotes
+            .iter()
+            .filter(|r| r.name != remote.name && !missing.iter().any(|m| m.name == r.name))
+            .map(|r| r.name.clone())
+            .collect_vec();
+
+        let result = result.map(|mut object| {
+            if let Some(e_tag) = object.e_tag.clone().filter(|_| !fallbacks.is_empty()) {
+                object.body = failover_bytestream(
+                    object.body,
+                    Arc::clone(&self.remotes),
+                    fallbacks,
+                    input,
+                    e_tag,
+                    object.content_range.as_deref(),
+                    object.content_length,
+                );
+            }
+            object
+        });
+
+        // Remotes the rule only reads from are not repaired, as the object is not meant to be written there.
+        let missing = missing
+            .into_iter()
+            .filter(|r| rule.map_or(true, |rule| rule.writes_to(&r.name)))
+            .collect_vec();
+        if let Ok(object) = &result {
+            if !missing.is_empty() {
+                // The total size is after the slash in `Content-Range` for ranged reads.
+                let size = object
+                    .content_range
+                    .as_deref()
+                    .and_then(|range| range.rsplit('/').next()?.parse().ok())
+                    .or(object.content_length);
+                self.read_repair(
+                    bucket,
+                    remote.name.clone(),
+                    missing.into_iter().map(|r| r.name.clone()).collect(),
+                    key,
+                    size,
+                );
+            }
+        }
+
+        let output = result
+            .map_err(convert_sdk_err)
+            .and_then(GetObjectOutput::try_from_aws)?;
+
+        Ok(S3Response::new(output))
+    }
+
+    #[instrument(skip_all, name = "s3s/head_object")]
+    async fn head_object(
+        &self,
+        req: S3Request<HeadObjectInput>,
+    ) -> S3Result<S3Response<HeadObjectOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::GetObject,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let read_remotes = self.read_remotes(bucket, bucket.rule(&req.input.key));
+
+        let input = HeadObjectInput::try_into_aws(req.input)?;
+
+        let ReadResult { result, .. } = read_object(
+            read_remotes,
+            bucket.hedge.as_ref(),
+            |remote| {
+                let input = input.clone();
+                async move {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::HeadObject { input, reply: tx })
+                        .await
+                        .ok()?;
+                    rx.await.ok()?
+                }
+            },
+            HeadObjectError::is_not_found,
+        )
+        .await;
+
+        let Some((result, remote)) = result else {
+            warn!("no remotes available!");
+            return Err(s3_error!(InternalError));
+        };
+
+        info!("ok (remote: {})", remote.name);
+
+        let output = result
+            .map_err(convert_sdk_err)
+            .and_then(HeadObjectOutput::try_from_aws)?;
+
+        Ok(S3Response::new(output))
+    }
+
+    #[instrument(skip_all, name = "s3s/get_object_tagging")]
+    async fn get_object_tagging(
+        &self,
+        req: S3Request<GetObjectTaggingInput>,
+    ) -> S3Result<S3Response<GetObjectTaggingOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::GetObjectTagging,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let read_remotes = self.read_remotes(bucket, bucket.rule(&req.input.key));
+
+        let input = GetObjectTaggingInput::try_into_aws(req.input)?;
+
+        let ReadResult { result, .. } = read_object(
+            read_remotes,
+            bucket.hedge.as_ref(),
+            |remote| {
+                let input = input.clone();
+                async move {
+                    let (tx, rx) = oneshot::channel();
+                    remote
+                        .tx
+                        .send(remote::RemoteMessage::GetObjectTagging { input, reply: tx })
+                        .await
+                        .ok()?;
+                    rx.await.ok()?
+                }
+            },
+            |e: &GetObjectTaggingError| e.code() == Some("NoSuchKey"),
+        )
+        .await;
+
+        let Some((result, remote)) = result else {
+            warn!("no remotes available!");
+            return Err(s3_error!(InternalError));
+        };
+
+        info!("ok (remote: {})", remote.name);
+
+        let output = result
+            .map_err(convert_sdk_err)
+            .and_then(GetObjectTaggingOutput::try_from_aws)?;
+
+        Ok(S3Response::new(output))
+    }
+
+    #[instrument(skip_all, name = "s3s/put_object_tagging")]
+    async fn put_object_tagging(
         &self,
-        req: S3Request<DeleteObjectsInput>,
-    ) -> S3Result<S3Response<DeleteObjectsOutput>> {
-        let input = DeleteObjectsInput::try_into_aws(req.input)?;
-        let results = futures::stream::iter(self.remotes.iter())
+        req: S3Request<PutObjectTaggingInput>,
+    ) -> S3Result<S3Response<PutObjectTaggingOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::PutObjectTagging,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let input = PutObjectTaggingInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::PutObjectTagging,
+            input.key.clone(),
+            None,
+            self.write_remotes(bucket, rule),
+        );
+        let results = futures::stream::iter(self.write_remotes(bucket, rule))
             .map(|remote| async {
                 let Some(result) = (try {
                     let (tx, rx) = oneshot::channel();
                     remote
                         .tx
-                        .send(remote::RemoteMessage::DeleteObjects {
+                        .send(remote::RemoteMessage::PutObjectTagging {
                             input: input.clone(),
                             reply: tx,
                         })
@@ -442,24 +1570,43 @@ impl S3 for S3Reproxy {
             .collect::<Vec<_>>()
             .await;
 
-        let output = output_remote_inconsistent(results)?;
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
 
-        Ok(S3Response::new(DeleteObjectsOutput::try_from_aws(output)?))
+        Ok(S3Response::new(PutObjectTaggingOutput::try_from_aws(
+            output,
+        )?))
     }
 
-    #[instrument(skip_all, name = "s3s/delete_object")]
-    async fn delete_object(
+    #[instrument(skip_all, name = "s3s/delete_object_tagging")]
+    async fn delete_object_tagging(
         &self,
-        req: S3Request<DeleteObjectInput>,
-    ) -> S3Result<S3Response<DeleteObjectOutput>> {
-        let input = DeleteObjectInput::try_into_aws(req.input)?;
-        let results = futures::stream::iter(self.remotes.iter())
+        req: S3Request<DeleteObjectTaggingInput>,
+    ) -> S3Result<S3Response<DeleteObjectTaggingOutput>> {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::DeleteObjectTagging,
+            Some(req.input.key.as_str()),
+        )
+        .await?;
+        let rule = bucket.rule(&req.input.key);
+        let input = DeleteObjectTaggingInput::try_into_aws(req.input)?;
+        let scope = RepairScope::new(
+            RepairOperation::DeleteObjectTagging,
+            input.key.clone(),
+            None,
+            self.write_remotes(bucket, rule),
+        );
+        let results = futures::stream::iter(self.write_remotes(bucket, rule))
             .map(|remote| async {
                 let Some(result) = (try {
                     let (tx, rx) = oneshot::channel();
                     remote
                         .tx
-                        .send(remote::RemoteMessage::DeleteObject {
+                        .send(remote::RemoteMessage::DeleteObjectTagging {
                             input: input.clone(),
                             reply: tx,
                         })
@@ -478,78 +1625,87 @@ impl S3 for S3Reproxy {
             .collect::<Vec<_>>()
             .await;
 
-        let output = output_remote_inconsistent(results)?;
+        let output = self
+            .output_remote_inconsistent(bucket, Some(scope), results)
+            .await?;
 
-        Ok(S3Response::new(DeleteObjectOutput::try_from_aws(output)?))
+        Ok(S3Response::new(DeleteObjectTaggingOutput::try_from_aws(
+            output,
+        )?))
     }
 
-    #[instrument(skip_all, name = "s3s/get_object")]
-    async fn get_object(
+    #[instrument(skip_all, fields(marker = &req.input.marker), name = "s3s/list_objects")]
+    async fn list_objects(
         &self,
-        req: S3Request<GetObjectInput>,
-    ) -> S3Result<S3Response<GetObjectOutput>> {
-        let read_remotes = self.remotes.iter().sorted_by(|a, b| {
-            b.read_request
-                .cmp(&a.read_request)
-                .then_with(|| b.priority.cmp(&a.priority))
-        });
-
-        let input = GetObjectInput::try_into_aws(req.input)?;
-
-        let Some((result, remote)) = ('request: {
-            for remote in read_remotes {
-                let Some(output) = (try {
-                    let (tx, rx) = oneshot::channel();
-                    remote
-                        .tx
-                        .send(remote::RemoteMessage::GetObject {
-                            input: input.clone(),
-                            reply: tx,
-                        })
-                        .await
-                        .ok()?;
-                    rx.await.ok()??
-                }) else {
-                    warn!("remote({:?}) request failed. skipping", remote.name);
-                    continue;
-                };
-                break 'request Some((output, remote.name.clone()));
-            }
-            None
-        }) else {
-            warn!("no remotes available!");
-            return Err(s3_error!(InternalError));
-        };
-
-        info!("ok (remote: {})", remote);
-
-        let output = result
-            .map_err(convert_sdk_err)
-            .and_then(GetObjectOutput::try_from_aws)?;
-
-        Ok(S3Response::new(output))
+        req: S3Request<ListObjectsInput>,
+    ) -> S3Result<S3Response<ListObjectsOutput>> {
+        info!("{:?}", &req);
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::ListBucket,
+            Some(req.input.prefix.as_deref().unwrap_or_default()),
+        )
+        .await?;
+
+        // The marker is the key to start after, so no token has to be issued for it.
+        let (page, next_marker) = self
+            .list_page(
+                bucket,
+                req.input.prefix.clone(),
+                req.input.delimiter.clone(),
+                req.input.max_keys,
+                req.input.marker.clone(),
+            )
+            .await?;
+
+        let output = AwsListObjectsOutput::builder()
+            .name(bucket.name.clone())
+            .set_prefix(req.input.prefix)
+            .set_delimiter(req.input.delimiter)
+            .set_marker(req.input.marker)
+            .set_max_keys(page.max_keys)
+            .set_is_truncated(page.is_truncated)
+            .set_next_marker(next_marker)
+            .set_contents(page.contents)
+            .set_common_prefixes(page.common_prefixes)
+            .build();
+
+        Ok(S3Response::new(ListObjectsOutput::try_from_aws(output)?))
     }
 
-    #[instrument(skip_all, name = "s3s/head_object")]
-    async fn head_object(
+    /// Versions are specific to each backend, so the listing comes from the first available remote as-is.
+    #[instrument(skip_all, fields(key_marker = &req.input.key_marker), name = "s3s/list_object_versions")]
+    async fn list_object_versions(
         &self,
-        req: S3Request<HeadObjectInput>,
-    ) -> S3Result<S3Response<HeadObjectOutput>> {
-        let read_remotes = self.remotes.iter().sorted_by(|a, b| {
-            b.read_request
-                .cmp(&a.read_request)
-                .then_with(|| b.priority.cmp(&a.priority))
-        });
+        req: S3Request<ListObjectVersionsInput>,
+    ) -> S3Result<S3Response<ListObjectVersionsOutput>> {
+        info!("{:?}", &req);
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::ListBucketVersions,
+            Some(req.input.prefix.as_deref().unwrap_or_default()),
+        )
+        .await?;
 
-        let input = HeadObjectInput::try_into_aws(req.input)?;
+        let read_remotes = self.read_remotes(bucket, bucket.list_rule(req.input.prefix.as_deref()));
+
+        let input = ListObjectVersionsInput::try_into_aws(req.input)?;
 
         let Some((result, remote)) = ('request: {
             for remote in read_remotes {
+                if remote.is_down() {
+                    info!("remote({:?}) is DOWN. skipping", remote.name);
+                    continue;
+                }
                 let Some(output) = (try {
                     let (tx, rx) = oneshot::channel();
                     remote
                         .tx
-                        .send(remote::RemoteMessage::HeadObject {
+                        .send(remote::RemoteMessage::ListObjectVersions {
                             input: input.clone(),
                             reply: tx,
                         })
@@ -570,11 +1726,12 @@ impl S3 for S3Reproxy {
 
         info!("ok (remote: {})", remote);
 
-        let output = result
-            .map_err(convert_sdk_err)
-            .and_then(HeadObjectOutput::try_from_aws)?;
+        let mut output = result.map_err(convert_sdk_err)?;
+        output.name = Some(bucket.name.clone());
 
-        Ok(S3Response::new(output))
+        Ok(S3Response::new(ListObjectVersionsOutput::try_from_aws(
+            output,
+        )?))
     }
 
     #[instrument(skip_all, fields(token = &req.input.continuation_token), name = "s3s/list_objects_v2")]
@@ -583,9 +1740,33 @@ impl S3 for S3Reproxy {
         req: S3Request<ListObjectsV2Input>,
     ) -> S3Result<S3Response<ListObjectsV2Output>> {
         info!("{:?}", &req);
-
-        let start_after = match req.input.continuation_token.clone() {
-            Some(continuation_token) => {
+        let bucket = self.bucket(&req.input.bucket)?;
+        self.authorize(
+            &req,
+            Some(bucket),
+            Action::ListBucket,
+            Some(req.input.prefix.as_deref().unwrap_or_default()),
+        )
+        .await?;
+
+        let start_after = match (req.input.continuation_token.clone(), &self.list_tokens) {
+            (Some(continuation_token), Some(signer)) => {
+                let prefix = req.input.prefix.as_deref().unwrap_or_default();
+                Some(
+                    signer
+                        .verify(
+                            &req.input.bucket,
+                            prefix,
+                            &continuation_token,
+                            SystemTime::now(),
+                        )
+                        .map_err(|e| {
+                            warn!("(intercepted) invalid continuation token: {}", e);
+                            S3Error::new(s3s::S3ErrorCode::InvalidToken)
+                        })?,
+                )
+            }
+            (Some(continuation_token), None) => {
                 let list = self
                     .db
                     .list_object_tokens
@@ -614,63 +1795,33 @@ impl S3 for S3Reproxy {
                     })?;
                 Some(list.start_after)
             }
-            None => None,
+            (None, _) => None,
         };
 
-        let read_remotes = self.remotes.iter().sorted_by(|a, b| {
-            b.read_request
-                .cmp(&a.read_request)
-                .then_with(|| b.priority.cmp(&a.priority))
-        });
-
         let start_after = start_after.or(req.input.start_after.clone());
 
-        let Some((result, remote)) = ('request: {
-            for remote in read_remotes {
-                let Some(output) = (try {
-                    let (tx, rx) = oneshot::channel();
-                    remote
-                        .tx
-                        .send(remote::RemoteMessage::ListObjects {
-                            prefix: req.input.prefix.clone(),
-                            delimiter: req.input.delimiter.clone(),
-                            max_keys: req.input.max_keys,
-                            start_after: start_after.clone(),
-                            reply: tx,
-                        })
-                        .await
-                        .ok()?;
-                    rx.await.ok()??
-                }) else {
-                    warn!("remote({:?}) request failed. skipping", remote.name);
-                    continue;
-                };
-                break 'request Some((output, remote.name.clone()));
-            }
-            None
-        }) else {
-            warn!("no remotes available!");
-            return Err(s3_error!(InternalError));
-        };
-
-        info!("ok (remote: {})", remote);
+        let (output, next_start_after) = self
+            .list_page(
+                bucket,
+                req.input.prefix.clone(),
+                req.input.delimiter.clone(),
+                req.input.max_keys,
+                start_after,
+            )
+            .await?;
 
-        let mut output = result
-            .map_err(convert_sdk_err)
-            .and_then(ListObjectsV2Output::try_from_aws)?;
+        let mut output = ListObjectsV2Output::try_from_aws(output)?;
 
+        output.start_after = req.input.start_after;
         output.continuation_token = req.input.continuation_token;
-        output.next_continuation_token = match output.next_continuation_token {
-            Some(_) => 'm: {
-                let Some(last) = output
-                    .contents
-                    .as_ref()
-                    .and_then(|e| e.last())
-                    .and_then(|e| e.key.clone())
-                else {
-                    break 'm None;
-                };
-
+        output.next_continuation_token = match (next_start_after, &self.list_tokens) {
+            (Some(last), Some(signer)) => Some(signer.issue(
+                &req.input.bucket,
+                req.input.prefix.as_deref().unwrap_or_default(),
+                &last,
+                SystemTime::now(),
+            )),
+            (Some(last), None) => {
                 let list = self
                     .db
                     .list_object_tokens
@@ -687,56 +1838,521 @@ impl S3 for S3Reproxy {
 
                 Some(list.inserted_id.as_object_id().unwrap().to_hex())
             }
-            None => None,
+            (None, _) => None,
         };
 
         Ok(S3Response::new(output))
     }
 }
 
-#[allow(clippy::type_complexity)]
-fn output_remote_inconsistent<T, E: Debug + ProvideErrorMetadata>(
-    results: Vec<(String, Result<T, ServiceError<E, HttpResponse>>)>,
-) -> Result<T, S3Error> {
-    let (successes, failures): (Vec<_>, Vec<_>) =
-        results
+/// Keys and remotes touched by a write.
+/// When only some of `remotes` succeed, a repair task is recorded for each key.
+struct RepairScope {
+    operation: RepairOperation,
+    keys: Vec<String>,
+    upload_id: Option<ObjectId>,
+    remotes: Vec<String>,
+}
+
+impl RepairScope {
+    fn new<'a>(
+        operation: RepairOperation,
+        keys: impl IntoIterator<Item = String>,
+        upload_id: Option<ObjectId>,
+        remotes: impl IntoIterator<Item = &'a S3Remote>,
+    ) -> Self {
+        Self {
+            operation,
+            keys: keys.into_iter().collect(),
+            upload_id,
+            remotes: remotes.into_iter().map(|r| r.name.clone()).collect(),
+        }
+    }
+
+    /// Tasks copying the outcome of the write from the `succeeded` remotes into the other remotes of the scope.
+    fn tasks(self, succeeded: Vec<String>, now: mongodb::bson::DateTime) -> Vec<RepairTask> {
+        let failed = self
+            .remotes
             .into_iter()
-            .partition_map(|(remote, result)| match result {
-                Ok(output) => Either::Left((remote, output)),
-                Err(e) => Either::Right((remote, e)),
-            });
+            .filter(|r| !succeeded.contains(r))
+            .collect_vec();
+        if succeeded.is_empty() || failed.is_empty() {
+            return vec![];
+        }
+
+        self.keys
+            .into_iter()
+            .map(|key| RepairTask {
+                id: None,
+                key,
+                operation: self.operation,
+                upload_id: self.upload_id,
+                succeeded_remotes: succeeded.clone(),
+                failed_remotes: failed.clone(),
+                attempts: 0,
+                created_at: now,
+                next_attempt_at: now,
+                resolved_at: None,
+            })
+            .collect()
+    }
+}
+
+impl S3Reproxy {
+    #[allow(clippy::type_complexity)]
+    async fn output_remote_inconsistent<T, E: Debug + ProvideErrorMetadata>(
+        &self,
+        bucket: &VirtualBucket,
+        scope: Option<RepairScope>,
+        results: Vec<(String, Result<T, ServiceError<E, HttpResponse>>)>,
+    ) -> Result<T, S3Error> {
+        // Remotes which were unreachable have no result, but still count against the quorum.
+        let expected = match &scope {
+            Some(scope) => scope.remotes.clone(),
+            None => results.iter().map(|(remote, _)| remote.clone()).collect(),
+        };
+
+        let (successes, failures): (Vec<_>, Vec<_>) =
+            results
+                .into_iter()
+                .partition_map(|(remote, result)| match result {
+                    Ok(output) => Either::Left((remote, output)),
+                    Err(e) => Either::Right((remote, e)),
+                });
+
+        let succeeded = successes
+            .iter()
+            .map(|(remote, _)| remote.clone())
+            .collect_vec();
+
+        let failed = expected
+            .iter()
+            .filter(|remote| !succeeded.contains(remote))
+            .cloned()
+            .collect_vec();
 
-    if failures.is_empty() {
-        let (remote, reply) = successes.into_iter().next().map_or_else(
-            || {
+        if failed.is_empty() {
+            let (remote, reply) = successes.into_iter().next().map_or_else(
+                || {
+                    warn!("no remotes available!");
+                    Err(S3Error::new(S3ErrorCode::InternalError))
+                },
+                Result::Ok,
+            )?;
+            info!("all remote ok (replied remote: {})", remote);
+            Ok(reply)
+        } else if successes.is_empty() {
+            let Some((remote, err)) = failures.into_iter().next() else {
                 warn!("no remotes available!");
-                Err(S3Error::new(S3ErrorCode::InternalError))
+                return Err(S3Error::new(S3ErrorCode::InternalError));
+            };
+            info!("all remote failed (replied remote: {})", remote);
+            Err(convert_sdk_err(err))?
+        } else {
+            error!("some remote failed (inconsisted).");
+            for (remote, _) in successes.iter() {
+                info!("remote({:?}) ok", remote);
+            }
+            for (remote, err) in failures {
+                error!("remote({:?}) failed: {:?}", remote, err);
+            }
+
+            // Writes missing the quorum are completed on the failed remotes as well, rather than rolled back:
+            // deleting the key from the succeeded remotes would not bring back an object it overwrote there.
+            // The client is told the write failed, and retrying it converges on the same state.
+            let quorum_met = self.write_quorum_met(bucket, &succeeded, &expected);
+            if let Some(scope) = scope {
+                self.enqueue_repair(scope, succeeded).await;
+            }
+
+            if !quorum_met {
+                error!("write quorum not met (failed remotes: {:?})", failed);
+                return Err(S3Error::with_message(
+                    S3ErrorCode::InternalError,
+                    format!(
+                        "Write quorum not met. Failed remote(s): {}",
+                        failed.join(", ")
+                    ),
+                ));
+            }
+
+            let (remote, reply) = successes.into_iter().next().unwrap();
+            info!("some remote ok (replied remote: {})", remote);
+            Ok(reply)
+        }
+    }
+
+    /// Copies `key` from `source` into the `missing` remotes in the background, within the `read_repair` limits.
+    fn read_repair(
+        &self,
+        bucket: &VirtualBucket,
+        source: String,
+        missing: Vec<String>,
+        key: String,
+        size: Option<i64>,
+    ) {
+        if size.map_or(true, |size| size > bucket.read_repair.max_object_size) {
+            info!(
+                "object is too large to read-repair (key: {}, size: {:?})",
+                key, size
+            );
+            return;
+        }
+
+        let Ok(permit) = Arc::clone(&bucket.read_repair_permits).try_acquire_owned() else {
+            warn!("too many read-repairs in progress. skipping (key: {})", key);
+            return;
+        };
+
+        info!(
+            "read-repairing remote(s) {:?} from remote({:?})",
+            missing, source
+        );
+
+        let remotes = Arc::clone(&self.remotes);
+        tokio::spawn(
+            async move {
+                let _permit = permit;
+                let Some(source) = remotes.iter().find(|r| r.name == source) else {
+                    return;
+                };
+                for target in remotes.iter().filter(|r| missing.contains(&r.name)) {
+                    if let Err(e) = replicate_object(source, target, &key).await {
+                        warn!("read-repair failed: {}", e);
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Lists a page of objects starting after `start_after`, from a single remote or merged from all of them depending on `list_mode`.
+    /// Also returns where the next page starts, if the listing is truncated.
+    async fn list_page(
+        &self,
+        bucket: &VirtualBucket,
+        prefix: Option<String>,
+        delimiter: Option<String>,
+        max_keys: Option<i32>,
+        start_after: Option<String>,
+    ) -> S3Result<(AwsListObjectsV2Output, Option<String>)> {
+        let (result, next_start_after) = match bucket.list_mode_for(prefix.as_deref()) {
+            ListMode::Single => {
+                let Some((result, remote)) = ('request: {
+                    for remote in self.read_remotes(bucket, bucket.list_rule(prefix.as_deref())) {
+                        if remote.is_down() {
+                            info!("remote({:?}) is DOWN. skipping", remote.name);
+                            continue;
+                        }
+                        let Some(output) = (try {
+                            let (tx, rx) = oneshot::channel();
+                            remote
+                                .tx
+                                .send(remote::RemoteMessage::ListObjects {
+                                    prefix: prefix.clone(),
+                                    delimiter: delimiter.clone(),
+                                    max_keys,
+                                    start_after: start_after.clone(),
+                                    reply: tx,
+                                })
+                                .await
+                                .ok()?;
+                            rx.await.ok()??
+                        }) else {
+                            warn!("remote({:?}) request failed. skipping", remote.name);
+                            continue;
+                        };
+                        break 'request Some((output, remote.name.clone()));
+                    }
+                    None
+                }) else {
+                    warn!("no remotes available!");
+                    return Err(s3_error!(InternalError));
+                };
+
+                info!("ok (remote: {})", remote);
+
+                let next_start_after = result
+                    .as_ref()
+                    .ok()
+                    .filter(|output| output.next_continuation_token.is_some())
+                    .and_then(|output| output.contents().last()?.key().map(str::to_owned));
+
+                (result, next_start_after)
+            }
+            ListMode::Merged => {
+                let healthy = self
+                    .read_remotes(bucket, bucket.list_rule(prefix.as_deref()))
+                    .into_iter()
+                    .filter(|r| !r.is_down())
+                    .collect_vec();
+                let readable = healthy
+                    .iter()
+                    .copied()
+                    .filter(|r| r.read_request)
+                    .collect_vec();
+                let remotes = if readable.is_empty() {
+                    healthy
+                } else {
+                    readable
+                };
+
+                let max_keys = max_keys.unwrap_or(1000);
+                let Some(result) = list_merged(
+                    remotes,
+                    prefix.clone(),
+                    delimiter.clone(),
+                    max_keys,
+                    start_after.clone(),
+                )
+                .await
+                else {
+                    warn!("no remotes available!");
+                    return Err(s3_error!(InternalError));
+                };
+
+                info!("ok (merged)");
+
+                let next_start_after = result
+                    .as_ref()
+                    .ok()
+                    .and_then(|list| list.next_start_after.clone());
+                let result = result.map(|list| {
+                    AwsListObjectsV2Output::builder()
+                        .name(bucket.name.clone())
+                        .set_prefix(prefix.clone())
+                        .set_delimiter(delimiter.clone())
+                        .max_keys(max_keys)
+                        .key_count((list.contents.len() + list.common_prefixes.len()) as i32)
+                        .is_truncated(list.is_truncated)
+                        .set_contents(Some(list.contents))
+                        .set_common_prefixes(Some(list.common_prefixes))
+                        .build()
+                });
+
+                (result, next_start_after)
+            }
+        };
+
+        let output = result.map_err(convert_sdk_err)?;
+
+        Ok((output, next_start_after))
+    }
+
+    /// Remotes to read from, in the order of the routing policy, limited to those `rule` allows reads from.
+    fn read_remotes(&self, bucket: &VirtualBucket, rule: Option<&RoutingRule>) -> Vec<&S3Remote> {
+        read_order(
+            self.bucket_remotes(bucket)
+                .into_iter()
+                .filter(|r| rule.map_or(true, |rule| rule.reads_from(&r.name))),
+            bucket.routing,
+            bucket.read_turn.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+
+    /// Checks that the request may perform `action` on the bucket or on each of `keys`.
+    /// The bucket policy decides first, and requests it neither allows nor denies fall back to the permissions of the access key.
+    /// For listings, `keys` is the listed prefix.
+    async fn authorize<'a, T>(
+        &self,
+        req: &S3Request<T>,
+        bucket: Option<&VirtualBucket>,
+        action: Action,
+        keys: impl IntoIterator<Item = &'a str>,
+    ) -> S3Result<()> {
+        check_presigned_expires(&req.uri)?;
+
+        let access_key = match &req.credentials {
+            Some(credentials) => {
+                let Some(access_key) = self.auth.access_key(&credentials.access_key).await? else {
+                    warn!(
+                        "(intercepted) unknown access key: {}",
+                        credentials.access_key
+                    );
+                    return Err(s3_error!(InvalidAccessKeyId));
+                };
+                Some(access_key)
+            }
+            None => None,
+        };
+
+        // The policy cannot lock out the keys managing it.
+        let policy = match bucket {
+            Some(bucket) if action.permission().is_some() => self.bucket_policy(bucket).await?,
+            _ => None,
+        };
+        let source_ip = req.extensions.get::<ClientAddr>().map(|addr| addr.0.ip());
+
+        let keys = keys.into_iter().map(Some).collect_vec();
+        let keys = if keys.is_empty() { vec![None] } else { keys };
+        for key in keys {
+            let decision = match (&policy, bucket) {
+                (Some(policy), Some(bucket)) => policy.evaluate(&PolicyRequest {
+                    action,
+                    bucket: &bucket.name,
+                    key,
+                    principal: access_key.as_ref().map(|k| k.access_key.as_str()),
+                    source_ip,
+                }),
+                _ => Decision::Default,
+            };
+
+            let allowed = match decision {
+                Decision::Allow => true,
+                Decision::Deny => false,
+                Decision::Default => {
+                    access_key
+                        .as_ref()
+                        .is_some_and(|k| match action.permission() {
+                            Some(permission) => k.allows(permission, key),
+                            None => k.is_unrestricted(),
+                        })
+                }
+            };
+            if !allowed {
+                warn!(
+                    "(intercepted) {} denied (access key: {:?}, key: {:?}, policy: {:?})",
+                    action.name(),
+                    access_key.as_ref().map(|k| &k.access_key),
+                    key,
+                    decision
+                );
+                return Err(s3_error!(AccessDenied));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The policy put on `bucket`, if any.
+    /// It is read from MongoDB only once the cached one has expired.
+    async fn bucket_policy(&self, bucket: &VirtualBucket) -> S3Result<Option<Arc<PolicyDocument>>> {
+        if let Some(policy) = bucket.cached_policy() {
+            return Ok(policy);
+        }
+
+        let document = self
+            .db
+            .bucket_policies
+            .find_one(doc! { "bucket": &bucket.name })
+            .await
+            .map_err(|e| {
+                error!("Failed to find bucket policy: {:?}", e);
+                S3Error::new(S3ErrorCode::InternalError)
+            })?;
+
+        let policy = match document {
+            Some(document) => match PolicyDocument::parse(&document.policy, &bucket.name) {
+                Ok(policy) => Some(Arc::new(policy)),
+                Err(e) => {
+                    error!("Stored bucket policy is invalid: {}", e);
+                    return Err(S3Error::new(S3ErrorCode::InternalError));
+                }
             },
-            Result::Ok,
-        )?;
-        info!("all remote ok (replied remote: {})", remote);
-        Ok(reply)
-    } else if successes.is_empty() {
-        let (remote, err) = failures.into_iter().next().unwrap();
-        info!("all remote failed (replied remote: {})", remote);
-        Err(convert_sdk_err(err))?
-    } else {
-        error!("some remote failed (inconsisted).");
-        for (remote, _) in successes.iter() {
-            info!("remote({:?}) ok", remote);
+            None => None,
+        };
+        bucket.cache_policy(policy.clone());
+        Ok(policy)
+    }
+
+    /// The virtual bucket named `name`.
+    fn bucket(&self, name: &str) -> S3Result<&VirtualBucket> {
+        self.buckets.iter().find(|b| b.name == name).ok_or_else(|| {
+            warn!("(intercepted) bucket not found");
+            s3_error!(NoSuchBucket)
+        })
+    }
+
+    /// Remotes of `bucket` which `rule` sends writes to.
+    fn write_remotes(&self, bucket: &VirtualBucket, rule: Option<&RoutingRule>) -> Vec<&S3Remote> {
+        self.bucket_remotes(bucket)
+            .into_iter()
+            .filter(|r| rule.map_or(true, |rule| rule.writes_to(&r.name)))
+            .collect()
+    }
+
+    /// Remotes backing `bucket`.
+    fn bucket_remotes(&self, bucket: &VirtualBucket) -> Vec<&S3Remote> {
+        self.remotes
+            .iter()
+            .filter(|r| r.bucket == bucket.name)
+            .collect()
+    }
+
+    /// Whether `succeeded` satisfies `write_quorum` out of `expected`, including every required remote.
+    fn write_quorum_met(
+        &self,
+        bucket: &VirtualBucket,
+        succeeded: &[String],
+        expected: &[String],
+    ) -> bool {
+        let required = self
+            .bucket_remotes(bucket)
+            .into_iter()
+            .filter(|r| r.required)
+            .map(|r| r.name.clone())
+            .collect_vec();
+
+        bucket.write_quorum.is_met(succeeded, expected, &required)
+    }
+
+    /// Records a repair task for each key of `scope` if some of its remotes did not succeed.
+    async fn enqueue_repair(&self, scope: RepairScope, succeeded: Vec<String>) {
+        let operation = scope.operation;
+        let tasks = scope.tasks(succeeded, mongodb::bson::DateTime::now());
+        let Some(task) = tasks.first() else {
+            return;
+        };
+
+        warn!(
+            "enqueueing repair of {:?} for remote(s) {:?}",
+            operation, task.failed_remotes
+        );
+
+        if let Err(e) = self.db.repair_tasks.insert_many(tasks).await {
+            error!("mongodb error: {:?}", e);
         }
-        for (remote, err) in failures {
-            error!("remote({:?}) failed: {:?}", remote, err);
+    }
+
+    /// Returns the key and version of a copy source, which must be in `bucket` itself.
+    fn parse_copy_source(
+        &self,
+        bucket: &VirtualBucket,
+        copy_source: &CopySource,
+    ) -> Result<(String, Option<String>), S3Error> {
+        match copy_source {
+            CopySource::Bucket {
+                bucket: source_bucket,
+                key,
+                version_id,
+            } => {
+                if **source_bucket != bucket.name {
+                    warn!("(intercepted) source bucket not found");
+                    return Err(s3_error!(NoSuchBucket));
+                }
+                Ok((key.to_string(), version_id.as_deref().map(str::to_owned)))
+            }
+            CopySource::AccessPoint { .. } => {
+                warn!("(intercepted) access point copy source is not supported");
+                Err(s3_error!(NotImplemented))
+            }
+        }
+    }
+
+    /// Uploads created before buckets were recorded belong to the first bucket.
+    fn upload_in_bucket(&self, bucket: &VirtualBucket, upload_bucket: Option<&str>) -> bool {
+        match upload_bucket {
+            Some(upload_bucket) => upload_bucket == bucket.name,
+            None => self
+                .buckets
+                .first()
+                .is_some_and(|first| first.name == bucket.name),
         }
-        let (remote, reply) = successes.into_iter().next().unwrap();
-        info!("some remote ok (replied remote: {})", remote);
-        Ok(reply)
     }
-}
 
-impl S3Reproxy {
     async fn initiate_multipart(
         &self,
+        bucket: &VirtualBucket,
         upload_id: String,
     ) -> Result<(ObjectId, Vec<(Option<&S3Remote>, RemoteMultipartUploadId)>), S3Error> {
         let id = ObjectId::parse_str(upload_id).map_err(|e| {
@@ -761,6 +2377,11 @@ impl S3Reproxy {
                 S3Error::new(S3ErrorCode::InvalidToken)
             })?;
 
+        if !self.upload_in_bucket(bucket, ids.bucket.as_deref()) {
+            warn!("(intercepted) upload_id belongs to another bucket.");
+            return Err(S3Error::new(S3ErrorCode::InvalidToken));
+        }
+
         let remotes = ids
             .upload_ids
             .into_iter()
@@ -769,10 +2390,63 @@ impl S3Reproxy {
                     self.remotes.iter().find(|r| r.name == upload.remote_name),
                     upload,
                 ),
-                PartUploadStatus::Cancelled => (None, upload),
+                PartUploadStatus::Cancelled | PartUploadStatus::Aborted => (None, upload),
             })
             .collect_vec();
 
         Ok((id, remotes))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn scope(remotes: &[&str]) -> RepairScope {
+        RepairScope {
+            operation: RepairOperation::PutObject,
+            keys: vec!["a".to_string(), "b".to_string()],
+            upload_id: None,
+            remotes: remotes.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn write_below_quorum_is_repaired_on_failed_remotes() {
+        let now = mongodb::bson::DateTime::now();
+        let tasks = scope(&["r1", "r2", "r3"]).tasks(vec!["r2".to_string()], now);
+
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|t| (
+                    t.key.as_str(),
+                    t.succeeded_remotes.clone(),
+                    t.failed_remotes.clone()
+                ))
+                .collect_vec(),
+            vec![
+                (
+                    "a",
+                    vec!["r2".to_string()],
+                    vec!["r1".to_string(), "r3".to_string()]
+                ),
+                (
+                    "b",
+                    vec!["r2".to_string()],
+                    vec!["r1".to_string(), "r3".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_repaired_without_a_succeeded_or_failed_remote() {
+        let now = mongodb::bson::DateTime::now();
+        assert!(scope(&["r1", "r2"]).tasks(vec![], now).is_empty());
+        assert!(scope(&["r1", "r2"])
+            .tasks(vec!["r1".to_string(), "r2".to_string()], now)
+            .is_empty());
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::s3_target::{glob_match, Permission};
+
+/// Operations a bucket policy can grant or deny, named as in IAM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    GetObject,
+    PutObject,
+    DeleteObject,
+    AbortMultipartUpload,
+    ListMultipartUploadParts,
+    GetObjectTagging,
+    PutObjectTagging,
+    DeleteObjectTagging,
+    ListAllMyBuckets,
+    GetBucketLocation,
+    ListBucket,
+    ListBucketVersions,
+    ListBucketMultipartUploads,
+    GetBucketPolicy,
+    PutBucketPolicy,
+    DeleteBucketPolicy,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GetObject => "s3:GetObject",
+            Self::PutObject => "s3:PutObject",
+            Self::DeleteObject => "s3:DeleteObject",
+            Self::AbortMultipartUpload => "s3:AbortMultipartUpload",
+            Self::ListMultipartUploadParts => "s3:ListMultipartUploadParts",
+            Self::GetObjectTagging => "s3:GetObjectTagging",
+            Self::PutObjectTagging => "s3:PutObjectTagging",
+            Self::DeleteObjectTagging => "s3:DeleteObjectTagging",
+            Self::ListAllMyBuckets => "s3:ListAllMyBuckets",
+            Self::GetBucketLocation => "s3:GetBucketLocation",
+            Self::ListBucket => "s3:ListBucket",
+            Self::ListBucketVersions => "s3:ListBucketVersions",
+            Self::ListBucketMultipartUploads => "s3:ListBucketMultipartUploads",
+            Self::GetBucketPolicy => "s3:GetBucketPolicy",
+            Self::PutBucketPolicy => "s3:PutBucketPolicy",
+            Self::DeleteBucketPolicy => "s3:DeleteBucketPolicy",
+        }
+    }
+
+    /// The access key permission granting the action.
+    /// Managing the bucket policy is reserved to keys with every permission on every key.
+    pub fn permission(&self) -> Option<Permission> {
+        match self {
+            Self::GetObject
+            | Self::ListMultipartUploadParts
+            | Self::GetObjectTagging
+            | Self::ListAllMyBuckets
+            | Self::GetBucketLocation
+            | Self::ListBucket
+            | Self::ListBucketVersions
+            | Self::ListBucketMultipartUploads => Some(Permission::Read),
+            Self::PutObject
+            | Self::AbortMultipartUpload
+            | Self::PutObjectTagging
+            | Self::DeleteObjectTagging => Some(Permission::Write),
+            Self::DeleteObject => Some(Permission::Delete),
+            Self::GetBucketPolicy | Self::PutBucketPolicy | Self::DeleteBucketPolicy => None,
+        }
+    }
+
+    /// Whether the resource of the action is an object rather than the bucket.
+    pub fn on_object(&self) -> bool {
+        matches!(
+            self,
+            Self::GetObject
+                | Self::PutObject
+                | Self::DeleteObject
+                | Self::AbortMultipartUpload
+                | Self::ListMultipartUploadParts
+                | Self::GetObjectTagging
+                | Self::PutObjectTagging
+                | Self::DeleteObjectTagging
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allow,
+    Deny,
+    /// No statement applies.
+    Default,
+}
+
+/// What a request is about, as seen by the policy.
+pub struct PolicyRequest<'a> {
+    pub action: Action,
+    pub bucket: &'a str,
+    /// The object key, or the listed prefix for listings.
+    pub key: Option<&'a str>,
+    /// Access key the request is signed with. `None` for anonymous requests.
+    pub principal: Option<&'a str>,
+    pub source_ip: Option<IpAddr>,
+}
+
+impl PolicyRequest<'_> {
+    fn resource(&self) -> String {
+        match self.key {
+            Some(key) if self.action.on_object() => format!("arn:aws:s3:::{}/{}", self.bucket, key),
+            _ => format!("arn:aws:s3:::{}", self.bucket),
+        }
+    }
+
+    fn condition_value(&self, key: &str) -> Option<String> {
+        match key.to_ascii_lowercase().as_str() {
+            "aws:sourceip" => self.source_ip.map(|ip| ip.to_string()),
+            "s3:prefix" if !self.action.on_object() => self.key.map(str::to_owned),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyDocument {
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub id: Option<String>,
+    pub statement: OneOrMany<Statement>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct Statement {
+    #[serde(default)]
+    pub sid: Option<String>,
+    pub effect: Effect,
+    pub principal: Principal,
+    pub action: OneOrMany<String>,
+    pub resource: OneOrMany<String>,
+    #[serde(default)]
+    pub condition: HashMap<ConditionOperator, HashMap<String, OneOrMany<String>>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// `"*"` for everyone including anonymous requests, or `{"AWS": [...]}` listing access keys.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Principal {
+    Any(String),
+    Aws {
+        #[serde(rename = "AWS")]
+        aws: OneOrMany<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
+pub enum ConditionOperator {
+    StringEquals,
+    StringNotEquals,
+    StringLike,
+    StringNotLike,
+    IpAddress,
+    NotIpAddress,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            Self::One(item) => std::slice::from_ref(item).iter(),
+            Self::Many(items) => items.iter(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Principal must be \"*\" or {{\"AWS\": ...}}, but got {0:?}")]
+    InvalidPrincipal(String),
+
+    #[error("Policy has invalid resource {0:?}")]
+    InvalidResource(String),
+}
+
+impl PolicyDocument {
+    /// Parses a policy of `bucket`, whose resources must all be within the bucket.
+    pub fn parse(json: &str, bucket: &str) -> Result<Self, PolicyError> {
+        let policy: PolicyDocument = serde_json::from_str(json)?;
+
+        let bucket_arn = format!("arn:aws:s3:::{}", bucket);
+        for statement in policy.statement.iter() {
+            if let Principal::Any(principal) = &statement.principal {
+                if principal != "*" {
+                    return Err(PolicyError::InvalidPrincipal(principal.clone()));
+                }
+            }
+            if let Some(resource) = statement
+                .resource
+                .iter()
+                .find(|r| **r != bucket_arn && !r.starts_with(&format!("{}/", bucket_arn)))
+            {
+                return Err(PolicyError::InvalidResource(resource.clone()));
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// An explicit deny in any statement takes precedence over allows.
+    pub fn evaluate(&self, request: &PolicyRequest) -> Decision {
+        let mut decision = Decision::Default;
+        for statement in self.statement.iter().filter(|s| s.applies(request)) {
+            match statement.effect {
+                Effect::Deny => return Decision::Deny,
+                Effect::Allow => decision = Decision::Allow,
+            }
+        }
+        decision
+    }
+}
+
+impl Statement {
+    fn applies(&self, request: &PolicyRequest) -> bool {
+        let principal = match &self.principal {
+            Principal::Any(_) => true,
+            Principal::Aws { aws } => aws
+                .iter()
+                .any(|p| p == "*" || Some(p.as_str()) == request.principal),
+        };
+
+        // Actions are case-insensitive.
+        let action = request.action.name().to_ascii_lowercase();
+        let resource = request.resource();
+
+        principal
+            && self
+                .action
+                .iter()
+                .any(|a| glob_match(&a.to_ascii_lowercase(), &action))
+            && self.resource.iter().any(|r| glob_match(r, &resource))
+            && self.condition.iter().all(|(operator, entries)| {
+                entries.iter().all(|(key, values)| {
+                    condition_holds(*operator, request.condition_value(key), values)
+                })
+            })
+    }
+}
+
+/// Negated operators hold when the request has no value for the key.
+fn condition_holds(
+    operator: ConditionOperator,
+    actual: Option<String>,
+    values: &OneOrMany<String>,
+) -> bool {
+    let Some(actual) = actual else {
+        return matches!(
+            operator,
+            ConditionOperator::StringNotEquals
+                | ConditionOperator::StringNotLike
+                | ConditionOperator::NotIpAddress
+        );
+    };
+
+    match operator {
+        ConditionOperator::StringEquals => values.iter().any(|v| *v == actual),
+        ConditionOperator::StringNotEquals => !values.iter().any(|v| *v == actual),
+        ConditionOperator::StringLike => values.iter().any(|v| glob_match(v, &actual)),
+        ConditionOperator::StringNotLike => !values.iter().any(|v| glob_match(v, &actual)),
+        ConditionOperator::IpAddress | ConditionOperator::NotIpAddress => {
+            let Ok(ip) = actual.parse::<IpAddr>() else {
+                return false;
+            };
+            let in_range = values.iter().any(|v| ip_in_cidr(ip, v));
+            in_range == (operator == ConditionOperator::IpAddress)
+        }
+    }
+}
+
+/// Whether `ip` is within `cidr`, e.g. `192.0.2.0/24`. A bare address matches only itself.
+fn ip_in_cidr(ip: IpAddr, cidr: &str) -> bool {
+    let (network, len) = match cidr.split_once('/') {
+        Some((network, len)) => match len.parse::<u32>() {
+            Ok(len) => (network, Some(len)),
+            Err(_) => return false,
+        },
+        None => (cidr, None),
+    };
+    let Ok(network) = network.parse::<IpAddr>() else {
+        return false;
+    };
+
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let len = len.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let len = len.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn policy(json: &str) -> PolicyDocument {
+        PolicyDocument::parse(json, "bucket").unwrap()
+    }
+
+    fn request<'a>(
+        action: Action,
+        key: Option<&'a str>,
+        principal: Option<&'a str>,
+        source_ip: Option<&str>,
+    ) -> PolicyRequest<'a> {
+        PolicyRequest {
+            action,
+            bucket: "bucket",
+            key,
+            principal,
+            source_ip: source_ip.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn deny_takes_precedence() {
+        let policy = policy(
+            r#"{
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Principal": "*",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::bucket/*"
+                    },
+                    {
+                        "Effect": "Deny",
+                        "Principal": "*",
+                        "Action": "s3:*",
+                        "Resource": "arn:aws:s3:::bucket/secret/*"
+                    }
+                ]
+            }"#,
+        );
+
+        let get = |key| policy.evaluate(&request(Action::GetObject, Some(key), None, None));
+        assert_eq!(get("public/a"), Decision::Allow);
+        assert_eq!(get("secret/a"), Decision::Deny);
+        assert_eq!(
+            policy.evaluate(&request(Action::PutObject, Some("public/a"), None, None)),
+            Decision::Default
+        );
+    }
+
+    #[test]
+    fn principal() {
+        let any = policy(
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}}"#,
+        );
+        let aws = policy(
+            r#"{"Statement": {"Effect": "Allow", "Principal": {"AWS": ["AKID"]}, "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}}"#,
+        );
+
+        let get = |policy: &PolicyDocument, principal| {
+            policy.evaluate(&request(Action::GetObject, Some("a"), principal, None))
+        };
+        assert_eq!(get(&any, None), Decision::Allow);
+        assert_eq!(get(&any, Some("OTHER")), Decision::Allow);
+        assert_eq!(get(&aws, Some("AKID")), Decision::Allow);
+        assert_eq!(get(&aws, Some("OTHER")), Decision::Default);
+        assert_eq!(get(&aws, None), Decision::Default);
+
+        assert!(matches!(
+            PolicyDocument::parse(
+                r#"{"Statement": {"Effect": "Allow", "Principal": "AKID", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}}"#,
+                "bucket"
+            ),
+            Err(PolicyError::InvalidPrincipal(p)) if p == "AKID"
+        ));
+    }
+
+    #[test]
+    fn resource_and_action_globbing() {
+        let policy = policy(
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "S3:Get*", "Resource": "arn:aws:s3:::bucket/logs/*.gz"}}"#,
+        );
+
+        let evaluate = |action, key| policy.evaluate(&request(action, Some(key), None, None));
+        assert_eq!(evaluate(Action::GetObject, "logs/a/b.gz"), Decision::Allow);
+        assert_eq!(
+            evaluate(Action::GetObjectTagging, "logs/a.gz"),
+            Decision::Allow
+        );
+        assert_eq!(evaluate(Action::GetObject, "logs/a.txt"), Decision::Default);
+        assert_eq!(evaluate(Action::GetObject, "other/a.gz"), Decision::Default);
+        assert_eq!(evaluate(Action::PutObject, "logs/a.gz"), Decision::Default);
+
+        for resource in ["arn:aws:s3:::other/*", "arn:aws:s3:::bucket2"] {
+            let json = format!(
+                r#"{{"Statement": {{"Effect": "Allow", "Principal": "*", "Action": "s3:*", "Resource": "{}"}}}}"#,
+                resource
+            );
+            assert!(matches!(
+                PolicyDocument::parse(&json, "bucket"),
+                Err(PolicyError::InvalidResource(r)) if r == resource
+            ));
+        }
+    }
+
+    #[test]
+    fn prefix_condition() {
+        let policy = policy(
+            r#"{
+                "Statement": {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": ["s3:ListBucket", "s3:GetObject"],
+                    "Resource": ["arn:aws:s3:::bucket", "arn:aws:s3:::bucket/*"],
+                    "Condition": {"StringLike": {"s3:prefix": "home/*"}}
+                }
+            }"#,
+        );
+
+        let list = |prefix| policy.evaluate(&request(Action::ListBucket, Some(prefix), None, None));
+        assert_eq!(list("home/a"), Decision::Allow);
+        assert_eq!(list("etc/"), Decision::Default);
+        // Object requests have no prefix.
+        assert_eq!(
+            policy.evaluate(&request(Action::GetObject, Some("home/a"), None, None)),
+            Decision::Default
+        );
+    }
+
+    #[test]
+    fn source_ip_condition() {
+        let policy = policy(
+            r#"{
+                "Statement": {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": "s3:GetObject",
+                    "Resource": "arn:aws:s3:::bucket/*",
+                    "Condition": {"IpAddress": {"aws:SourceIp": ["192.0.2.0/24", "2001:db8::/32"]}}
+                }
+            }"#,
+        );
+
+        let get = |ip| policy.evaluate(&request(Action::GetObject, Some("a"), None, ip));
+        assert_eq!(get(Some("192.0.2.10")), Decision::Allow);
+        assert_eq!(get(Some("2001:db8::1")), Decision::Allow);
+        assert_eq!(get(Some("198.51.100.1")), Decision::Default);
+        assert_eq!(get(None), Decision::Default);
+    }
+
+    #[test]
+    fn negated_conditions_hold_without_value() {
+        let policy = policy(
+            r#"{
+                "Statement": [
+                    {
+                        "Effect": "Deny",
+                        "Principal": "*",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::bucket/*",
+                        "Condition": {"NotIpAddress": {"aws:SourceIp": "192.0.2.0/24"}}
+                    },
+                    {
+                        "Effect": "Allow",
+                        "Principal": "*",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::bucket/*",
+                        "Condition": {"StringNotLike": {"s3:prefix": "secret/*"}}
+                    }
+                ]
+            }"#,
+        );
+
+        let get = |ip| policy.evaluate(&request(Action::GetObject, Some("a"), None, ip));
+        assert_eq!(get(Some("192.0.2.10")), Decision::Allow);
+        assert_eq!(get(Some("198.51.100.1")), Decision::Deny);
+        assert_eq!(get(None), Decision::Deny);
+    }
+
+    #[test]
+    fn ip_in_cidr_masks() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        assert!(ip_in_cidr(ip("203.0.113.7"), "0.0.0.0/0"));
+        assert!(ip_in_cidr(ip("192.0.2.1"), "192.0.2.1/32"));
+        assert!(!ip_in_cidr(ip("192.0.2.2"), "192.0.2.1/32"));
+        assert!(ip_in_cidr(ip("192.0.2.1"), "192.0.2.1"));
+        assert!(!ip_in_cidr(ip("192.0.2.2"), "192.0.2.1"));
+        assert!(ip_in_cidr(ip("192.0.2.255"), "192.0.2.0/24"));
+        assert!(!ip_in_cidr(ip("192.0.3.0"), "192.0.2.0/24"));
+
+        assert!(ip_in_cidr(ip("2001:db8::1"), "::/0"));
+        assert!(ip_in_cidr(ip("2001:db8::1"), "2001:db8::/64"));
+        assert!(!ip_in_cidr(ip("2001:db8:0:1::1"), "2001:db8::/64"));
+        assert!(ip_in_cidr(ip("2001:db8::1"), "2001:db8::1/128"));
+        assert!(!ip_in_cidr(ip("2001:db8::2"), "2001:db8::1/128"));
+
+        // Families never match each other, and malformed ranges match nothing.
+        assert!(!ip_in_cidr(ip("192.0.2.1"), "::/0"));
+        assert!(!ip_in_cidr(ip("2001:db8::1"), "0.0.0.0/0"));
+        assert!(!ip_in_cidr(ip("192.0.2.1"), "192.0.2.0/x"));
+        assert!(!ip_in_cidr(ip("192.0.2.1"), "not an address"));
+    }
+}
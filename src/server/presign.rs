@@ -0,0 +1,206 @@
+use std::time::SystemTime;
+
+use aws_sdk_s3::config::{Credentials, Region};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::put_object::PutObjectError;
+use aws_sdk_s3::presigning::{PresigningConfig, PresigningConfigError};
+use aws_sdk_s3::Client;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use thiserror::Error;
+
+use crate::config::s3_target::Config;
+use crate::config::{PresignArgs, PresignMethod};
+
+#[derive(Error, Debug)]
+pub enum PresignError {
+    #[error("bucket {0:?} is not configured")]
+    UnknownBucket(String),
+
+    #[error("access key {0:?} is not configured")]
+    UnknownAccessKey(String),
+
+    #[error("invalid expiry: {0}")]
+    Expiry(#[from] PresigningConfigError),
+
+    #[error("failed to presign GetObject: {0:?}")]
+    GetObject(SdkError<GetObjectError, HttpResponse>),
+
+    #[error("failed to presign PutObject: {0:?}")]
+    PutObject(SdkError<PutObjectError, HttpResponse>),
+}
+
+/// Presigns a request to the proxy itself with one of its configured access keys.
+/// The URL is verified like any other SigV4 request, so it carries the permissions of the key.
+/// It is signed as of `now`, and expires `args.expires_in` later.
+pub(crate) async fn presign(
+    config: &Config,
+    args: &PresignArgs,
+    now: SystemTime,
+) -> Result<String, PresignError> {
+    if !config.buckets().iter().any(|b| b.name == args.bucket) {
+        return Err(PresignError::UnknownBucket(args.bucket.clone()));
+    }
+
+    let (access_key, secret_key) = match &args.access_key {
+        None => (&config.access_key, &config.secret_key),
+        Some(name) if *name == config.access_key => (&config.access_key, &config.secret_key),
+        Some(name) => config
+            .access_keys
+            .iter()
+            .find(|k| k.access_key == *name)
+            .map(|k| (&k.access_key, &k.secret_key))
+            .ok_or_else(|| PresignError::UnknownAccessKey(name.clone()))?,
+    };
+
+    let s3_config = aws_sdk_s3::config::Builder::new()
+        .endpoint_url(&args.endpoint)
+        .credentials_provider(Credentials::new(
+            access_key,
+            secret_key,
+            None,
+            None,
+            "loaded-from-s3reproxy-config",
+        ))
+        .region(Region::new(args.region.clone()))
+        .force_path_style(true)
+        .behavior_version_latest()
+        .build();
+    let client = Client::from_conf(s3_config);
+
+    let presigning = PresigningConfig::builder()
+        .start_time(now)
+        .expires_in(*args.expires_in)
+        .build()?;
+    let request = match args.method {
+        PresignMethod::Get => client
+            .get_object()
+            .bucket(&args.bucket)
+            .key(&args.key)
+            .presigned(presigning)
+            .await
+            .map_err(PresignError::GetObject)?,
+        PresignMethod::Put => client
+            .put_object()
+            .bucket(&args.bucket)
+            .key(&args.key)
+            .presigned(presigning)
+            .await
+            .map_err(PresignError::PutObject)?,
+    };
+
+    Ok(request.uri().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use s3s::dto::{GetObjectInput, GetObjectOutput};
+    use s3s::service::{S3Service, S3ServiceBuilder};
+    use s3s::{S3Request, S3Response, S3Result, S3};
+
+    use super::*;
+    use crate::config::s3_target::AccessKey;
+    use crate::db::MongoDB;
+    use crate::server::auth::ReproxyAuth;
+    use pretty_assertions::assert_eq;
+
+    /// Answers every authenticated GetObject.
+    struct Authenticated;
+
+    #[async_trait]
+    impl S3 for Authenticated {
+        async fn get_object(
+            &self,
+            _req: S3Request<GetObjectInput>,
+        ) -> S3Result<S3Response<GetObjectOutput>> {
+            Ok(S3Response::new(GetObjectOutput::default()))
+        }
+    }
+
+    fn config() -> Config {
+        serde_yaml::from_str("access_key: AKID\nsecret_key: SECRET\nbucket: bucket\n").unwrap()
+    }
+
+    fn args(expires_in: Duration) -> PresignArgs {
+        PresignArgs {
+            method: PresignMethod::Get,
+            bucket: "bucket".to_string(),
+            key: "key".to_string(),
+            expires_in: expires_in.into(),
+            endpoint: "http://localhost:9000".to_string(),
+            access_key: None,
+            region: "us-east-1".to_string(),
+        }
+    }
+
+    async fn service(config: &Config) -> S3Service {
+        // Config keys are found without querying the database.
+        let client = mongodb::Client::with_uri_str("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        let auth = ReproxyAuth::new(
+            vec![AccessKey::full(
+                config.access_key.clone(),
+                config.secret_key.clone(),
+            )],
+            Arc::new(MongoDB::new(client, "test")),
+        );
+
+        let mut builder = S3ServiceBuilder::new(Authenticated);
+        builder.set_auth(auth);
+        builder.build()
+    }
+
+    /// s3s answers errors, including failed authentication, with their status.
+    async fn get(service: &S3Service, url: &str) -> hyper::StatusCode {
+        let req = hyper::Request::builder()
+            .method(hyper::Method::GET)
+            .uri(url)
+            .header(hyper::header::HOST, "localhost:9000")
+            .body(s3s::Body::empty())
+            .unwrap();
+        service.call(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn presigned_url_is_accepted_until_it_expires() {
+        let config = config();
+        let service = service(&config).await;
+
+        let now = SystemTime::now();
+
+        let url = presign(&config, &args(Duration::from_secs(60)), now)
+            .await
+            .unwrap();
+        assert_eq!(get(&service, &url).await, hyper::StatusCode::OK);
+
+        // Signed two minutes ago, so it expired a minute ago.
+        let url = presign(
+            &config,
+            &args(Duration::from_secs(60)),
+            now - Duration::from_secs(120),
+        )
+        .await
+        .unwrap();
+        assert_eq!(get(&service, &url).await, hyper::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn unknown_bucket_is_not_presigned() {
+        let e = presign(
+            &config(),
+            &PresignArgs {
+                bucket: "other".to_string(),
+                ..args(Duration::from_secs(60))
+            },
+            SystemTime::now(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, PresignError::UnknownBucket(b) if b == "other"));
+    }
+}
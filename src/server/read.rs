@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::ServiceError;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use itertools::Itertools;
+use tracing::{info, warn};
+
+use crate::config::s3_target::{HedgeConfig, RoutingPolicy};
+
+use super::remote::S3Remote;
+
+/// Orders the remotes to read from: read remotes first, then by priority, then by `policy` among remotes of the same priority.
+/// Backfilling remotes are left out.
+pub fn read_order<'a>(
+    remotes: impl IntoIterator<Item = &'a S3Remote>,
+    policy: RoutingPolicy,
+    turn: usize,
+) -> Vec<&'a S3Remote> {
+    let tiers = remotes
+        .into_iter()
+        .filter(|r| !r.is_backfilling())
+        .sorted_by(|a, b| {
+            b.read_request
+                .cmp(&a.read_request)
+                .then_with(|| b.priority.cmp(&a.priority))
+        })
+        .chunk_by(|r| (r.read_request, r.priority));
+
+    let mut order = vec![];
+    for (_, tier) in &tiers {
+        let mut tier = tier.collect_vec();
+        match policy {
+            RoutingPolicy::Static => {}
+            RoutingPolicy::Latency => {
+                tier.sort_by(|a, b| a.status().cost().total_cmp(&b.status().cost()))
+            }
+            RoutingPolicy::RoundRobin => {
+                let len = tier.len();
+                tier.rotate_left(turn % len);
+            }
+        }
+        order.extend(tier);
+    }
+    order
+}
+
+pub struct ReadResult<'a, T, E> {
+    /// The first answer other than "not found", or the first "not found" if every remote answered so.
+    #[allow(clippy::type_complexity)]
+    pub result: Option<(Result<T, ServiceError<E, HttpResponse>>, &'a S3Remote)>,
+    /// Remotes which answered that the object does not exist.
+    pub missing: Vec<&'a S3Remote>,
+}
+
+/// Sends a read request to `candidates` in order until one has the object.
+/// Remotes answering "not found" are skipped, and non-read remotes are only consulted when a read remote could not answer.
+/// With `hedge`, the next read remote is also asked when no remote has answered within the delay.
+/// The first answer wins and the pending requests are dropped: a remote skips them if still queued and aborts them if in flight.
+pub async fn read_object<'a, T, E, F, Fut>(
+    candidates: impl IntoIterator<Item = &'a S3Remote>,
+    hedge: Option<&HedgeConfig>,
+    request: F,
+    is_not_found: fn(&E) -> bool,
+) -> ReadResult<'a, T, E>
+where
+    F: Fn(&'a S3Remote) -> Fut + Sync,
+    Fut: Future<Output = Option<Result<T, ServiceError<E, HttpResponse>>>> + Send,
+    T: Send,
+    E: Send,
+{
+    let mut candidates = candidates.into_iter().collect::<VecDeque<_>>();
+    let (delay, max_hedges) = match hedge {
+        Some(hedge) => (*hedge.delay, hedge.max_hedges),
+        None => (Duration::MAX, 0),
+    };
+
+    let send = |remote: &'a S3Remote| {
+        let output = request(remote);
+        async move { (remote, output.await) }
+    };
+
+    let mut in_flight = FuturesUnordered::new();
+    let mut not_found = None;
+    let mut missing = vec![];
+    let mut readable_unavailable = false;
+    let mut hedges = 0;
+
+    loop {
+        if in_flight.is_empty() {
+            // Non-read remotes are only consulted when a read remote could not answer.
+            let last_resort = not_found.is_none() || readable_unavailable;
+            match next_candidate(&mut candidates, last_resort, &mut readable_unavailable) {
+                Some(remote) => in_flight.push(send(remote)),
+                None => break,
+            }
+        }
+
+        let can_hedge = hedges < max_hedges && candidates.front().is_some_and(|r| r.read_request);
+
+        tokio::select! {
+            Some((remote, output)) = in_flight.next() => match output {
+                None => {
+                    warn!("remote({:?}) request failed. skipping", remote.name);
+                    readable_unavailable |= remote.read_request;
+                }
+                Some(Err(e)) if is_not_found(e.err()) => {
+                    info!(
+                        "remote({:?}) does not have the object. trying next",
+                        remote.name
+                    );
+                    not_found.get_or_insert((Err(e), remote));
+                    missing.push(remote);
+                }
+                Some(output) => {
+                    return ReadResult {
+                        result: Some((output, remote)),
+                        missing,
+                    };
+                }
+            },
+            _ = tokio::time::sleep(delay), if can_hedge => {
+                if let Some(remote) = next_candidate(&mut candidates, false, &mut readable_unavailable) {
+                    info!("no answer within {:?}. hedging with remote({:?})", delay, remote.name);
+                    hedges += 1;
+                    in_flight.push(send(remote));
+                }
+            }
+        }
+    }
+
+    ReadResult {
+        result: not_found,
+        missing,
+    }
+}
+
+/// Pops the next remote to ask, skipping DOWN remotes.
+fn next_candidate<'a>(
+    candidates: &mut VecDeque<&'a S3Remote>,
+    last_resort: bool,
+    readable_unavailable: &mut bool,
+) -> Option<&'a S3Remote> {
+    while let Some(remote) = candidates.pop_front() {
+        if !remote.read_request && !last_resort {
+            candidates.push_front(remote);
+            return None;
+        }
+        if remote.is_down() {
+            info!("remote({:?}) is DOWN. skipping", remote.name);
+            *readable_unavailable |= remote.read_request;
+            continue;
+        }
+        return Some(remote);
+    }
+    None
+}
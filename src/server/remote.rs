@@ -1,41 +1,125 @@
 use aws_sdk_s3::config::{Credentials, Region, StalledStreamProtectionConfig};
 use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::abort_multipart_upload::{
+    AbortMultipartUploadError, AbortMultipartUploadInput, AbortMultipartUploadOutput,
+};
 use aws_sdk_s3::operation::complete_multipart_upload::{
     CompleteMultipartUploadError, CompleteMultipartUploadInput, CompleteMultipartUploadOutput,
 };
+use aws_sdk_s3::operation::copy_object::{CopyObjectError, CopyObjectInput, CopyObjectOutput};
 use aws_sdk_s3::operation::create_multipart_upload::{
     CreateMultipartUploadError, CreateMultipartUploadInput, CreateMultipartUploadOutput,
 };
 use aws_sdk_s3::operation::delete_object::{
     DeleteObjectError, DeleteObjectInput, DeleteObjectOutput,
 };
+use aws_sdk_s3::operation::delete_object_tagging::{
+    DeleteObjectTaggingError, DeleteObjectTaggingInput, DeleteObjectTaggingOutput,
+};
 use aws_sdk_s3::operation::delete_objects::{
     DeleteObjectsError, DeleteObjectsInput, DeleteObjectsOutput,
 };
 use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectInput, GetObjectOutput};
+use aws_sdk_s3::operation::get_object_tagging::{
+    GetObjectTaggingError, GetObjectTaggingInput, GetObjectTaggingOutput,
+};
 use aws_sdk_s3::operation::head_object::{HeadObjectError, HeadObjectInput, HeadObjectOutput};
+use aws_sdk_s3::operation::list_object_versions::{
+    ListObjectVersionsError, ListObjectVersionsInput, ListObjectVersionsOutput,
+};
 use aws_sdk_s3::operation::list_objects_v2::{ListObjectsV2Error, ListObjectsV2Output};
+use aws_sdk_s3::operation::list_parts::{ListPartsError, ListPartsInput, ListPartsOutput};
 use aws_sdk_s3::operation::put_object::{PutObjectError, PutObjectInput, PutObjectOutput};
+use aws_sdk_s3::operation::put_object_tagging::{
+    PutObjectTaggingError, PutObjectTaggingInput, PutObjectTaggingOutput,
+};
 use aws_sdk_s3::operation::upload_part::{UploadPartError, UploadPartInput, UploadPartOutput};
+use aws_sdk_s3::operation::upload_part_copy::{
+    UploadPartCopyError, UploadPartCopyInput, UploadPartCopyOutput,
+};
 use aws_sdk_s3::Client;
 use aws_smithy_runtime_api::client::orchestrator;
 use aws_smithy_runtime_api::client::result::ServiceError;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use std::fmt::Debug;
-use tokio::sync::{mpsc, oneshot};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinSet;
 use tracing::{info, info_span, instrument, warn, Instrument};
 
-use crate::config::s3_target::S3Target;
+use crate::config::s3_target::{CircuitBreakerConfig, S3Target};
 use crate::config::S3ReproxySetup;
 
 #[derive(Debug)]
 pub struct S3Remote {
     pub name: String,
+    /// Name of the virtual bucket this remote backs.
+    pub bucket: String,
     pub priority: u32,
     pub read_request: bool,
+    pub required: bool,
     pub tx: mpsc::Sender<RemoteMessage>,
+    backfilling: AtomicBool,
+    status: watch::Receiver<RemoteStatus>,
+}
+
+impl S3Remote {
+    /// Whether the remote is still being backfilled and must not serve reads.
+    pub fn is_backfilling(&self) -> bool {
+        self.backfilling.load(Ordering::Relaxed)
+    }
+
+    pub fn set_backfilling(&self, backfilling: bool) {
+        self.backfilling.store(backfilling, Ordering::Relaxed);
+    }
+
+    /// Whether the last request or health check failed to reach the remote.
+    pub fn is_down(&self) -> bool {
+        self.status.borrow().health == Some(false)
+    }
+
+    pub fn status(&self) -> RemoteStatus {
+        *self.status.borrow()
+    }
+}
+
+/// Weight of the latest request in the moving averages of `RemoteStatus`.
+const STATUS_EWMA_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoteStatus {
+    /// `None` until the first request or health check has finished.
+    pub health: Option<bool>,
+    /// Moving average of the time until a response, for requests which reached the remote.
+    pub latency: Option<Duration>,
+    /// Moving average of the share of requests which did not reach the remote.
+    pub error_rate: f64,
+}
+
+impl RemoteStatus {
+    fn record(&mut self, healthy: bool, latency: Option<Duration>) {
+        let failure = if healthy { 0.0 } else { 1.0 };
+        self.error_rate += STATUS_EWMA_WEIGHT * (failure - self.error_rate);
+
+        if let (true, Some(latency)) = (healthy, latency) {
+            self.latency = Some(match self.latency {
+                Some(average) => {
+                    average.mul_f64(1.0 - STATUS_EWMA_WEIGHT) + latency.mul_f64(STATUS_EWMA_WEIGHT)
+                }
+                None => latency,
+            });
+        }
+    }
+
+    /// Expected time until a successful response, in seconds. Remotes without any sample cost nothing, so they get tried.
+    pub fn cost(&self) -> f64 {
+        let latency = self.latency.map_or(0.0, |latency| latency.as_secs_f64());
+        latency / (1.0 - self.error_rate).max(0.05)
+    }
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum RemoteMessage {
     HealthCheck {
         reply: oneshot::Sender<bool>,
@@ -54,6 +138,17 @@ pub enum RemoteMessage {
             >,
         >,
     },
+    ListObjectVersions {
+        input: ListObjectVersionsInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    ListObjectVersionsOutput,
+                    ServiceError<ListObjectVersionsError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
     HeadObject {
         input: HeadObjectInput,
         reply: oneshot::Sender<
@@ -78,6 +173,18 @@ pub enum RemoteMessage {
             >,
         >,
     },
+    /// Server-side copy within this remote.
+    /// `input.copy_source` is ignored; the source is rebuilt against this remote's bucket from `source_key` and `source_version_id`.
+    CopyObject {
+        input: CopyObjectInput,
+        source_key: String,
+        source_version_id: Option<String>,
+        reply: oneshot::Sender<
+            Option<
+                Result<CopyObjectOutput, ServiceError<CopyObjectError, orchestrator::HttpResponse>>,
+            >,
+        >,
+    },
     DeleteObject {
         input: DeleteObjectInput,
         reply: oneshot::Sender<
@@ -119,6 +226,20 @@ pub enum RemoteMessage {
             >,
         >,
     },
+    /// Same as `CopyObject`, `input.copy_source` is rebuilt from `source_key` and `source_version_id`.
+    UploadPartCopy {
+        input: UploadPartCopyInput,
+        source_key: String,
+        source_version_id: Option<String>,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    UploadPartCopyOutput,
+                    ServiceError<UploadPartCopyError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
     CompleteMultiPartUpload {
         input: CompleteMultipartUploadInput,
         reply: oneshot::Sender<
@@ -130,12 +251,252 @@ pub enum RemoteMessage {
             >,
         >,
     },
+    GetObjectTagging {
+        input: GetObjectTaggingInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    GetObjectTaggingOutput,
+                    ServiceError<GetObjectTaggingError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
+    PutObjectTagging {
+        input: PutObjectTaggingInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    PutObjectTaggingOutput,
+                    ServiceError<PutObjectTaggingError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
+    DeleteObjectTagging {
+        input: DeleteObjectTaggingInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    DeleteObjectTaggingOutput,
+                    ServiceError<DeleteObjectTaggingError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
+    AbortMultiPartUpload {
+        input: AbortMultipartUploadInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<
+                    AbortMultipartUploadOutput,
+                    ServiceError<AbortMultipartUploadError, orchestrator::HttpResponse>,
+                >,
+            >,
+        >,
+    },
+    ListParts {
+        input: ListPartsInput,
+        reply: oneshot::Sender<
+            Option<
+                Result<ListPartsOutput, ServiceError<ListPartsError, orchestrator::HttpResponse>>,
+            >,
+        >,
+    },
     Shutdown,
 }
 
+impl RemoteMessage {
+    /// Whether the sender has stopped waiting for the reply of a read, e.g. one that lost a hedge.
+    /// Writes are always sent: a client going away must not leave the remotes diverged.
+    fn is_cancelled(&self) -> bool {
+        match self {
+            RemoteMessage::ListObjects { reply, .. } => reply.is_closed(),
+            RemoteMessage::ListObjectVersions { reply, .. } => reply.is_closed(),
+            RemoteMessage::GetObject { reply, .. } => reply.is_closed(),
+            RemoteMessage::HeadObject { reply, .. } => reply.is_closed(),
+            RemoteMessage::GetObjectTagging { reply, .. } => reply.is_closed(),
+            RemoteMessage::PutObject { .. }
+            | RemoteMessage::CopyObject { .. }
+            | RemoteMessage::DeleteObject { .. }
+            | RemoteMessage::DeleteObjects { .. }
+            | RemoteMessage::CreateMultiPartUpload { .. }
+            | RemoteMessage::UploadPart { .. }
+            | RemoteMessage::UploadPartCopy { .. }
+            | RemoteMessage::CompleteMultiPartUpload { .. }
+            | RemoteMessage::PutObjectTagging { .. }
+            | RemoteMessage::DeleteObjectTagging { .. }
+            | RemoteMessage::AbortMultiPartUpload { .. }
+            | RemoteMessage::ListParts { .. }
+            | RemoteMessage::HealthCheck { .. }
+            | RemoteMessage::Shutdown => false,
+        }
+    }
+
+    /// Replies as if the remote did not respond, without sending the request.
+    fn reject(self) {
+        match self {
+            RemoteMessage::HealthCheck { reply } => {
+                let _ = reply.send(false);
+            }
+            RemoteMessage::ListObjects { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::ListObjectVersions { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::GetObject { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::PutObject { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::CopyObject { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::DeleteObject { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::DeleteObjects { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::HeadObject { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::CreateMultiPartUpload { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::UploadPart { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::UploadPartCopy { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::CompleteMultiPartUpload { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::GetObjectTagging { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::PutObjectTagging { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::DeleteObjectTagging { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::AbortMultiPartUpload { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::ListParts { reply, .. } => {
+                let _ = reply.send(None);
+            }
+            RemoteMessage::Shutdown => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed,
+    /// Requests fail fast since the given instant.
+    Open(Instant),
+    /// The next request is let through as a probe.
+    HalfOpen,
+}
+
+/// Health of a remote as seen by its task, shared through `S3Remote::status`, plus its circuit breaker.
+struct RemoteHealth {
+    status: watch::Sender<RemoteStatus>,
+    circuit: CircuitState,
+    consecutive_failures: u32,
+    config: CircuitBreakerConfig,
+    /// When the request in progress was started.
+    started_at: Option<Instant>,
+}
+
+impl RemoteHealth {
+    fn new(status: watch::Sender<RemoteStatus>, config: CircuitBreakerConfig) -> Self {
+        Self {
+            status,
+            circuit: CircuitState::Closed,
+            consecutive_failures: 0,
+            config,
+            started_at: None,
+        }
+    }
+
+    /// Starts timing a request whose outcome is passed to `map_health`.
+    fn begin(&mut self) {
+        self.started_at = Some(Instant::now());
+    }
+
+    /// Forgets the request in progress, which was abandoned before the remote answered.
+    /// An abandoned request says nothing about the remote, so it is not recorded.
+    fn abandon(&mut self) {
+        self.started_at = None;
+    }
+
+    /// Whether a request, including a health check, may reach the remote now.
+    /// An open circuit turns half-open once `open_duration` has elapsed, letting the next request through as the probe.
+    /// Requests are handled one at a time, so only a single probe is in flight while half-open.
+    fn admit(&mut self) -> bool {
+        match self.circuit {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open(since) if since.elapsed() >= *self.config.open_duration => {
+                info!("circuit is HALF-OPEN. probing");
+                self.circuit = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::Open(_) => false,
+        }
+    }
+
+    /// Whether `msg` may be sent to the remote, in which case it is timed from now.
+    /// Only shutdown is never short-circuited.
+    fn allows(&mut self, msg: &RemoteMessage) -> bool {
+        let allowed = matches!(msg, RemoteMessage::Shutdown) || self.admit();
+        if allowed {
+            self.begin();
+        }
+        allowed
+    }
+
+    fn record(&mut self, healthy: bool) {
+        if healthy {
+            self.consecutive_failures = 0;
+            if self.circuit != CircuitState::Closed {
+                info!("circuit is CLOSED");
+                self.circuit = CircuitState::Closed;
+            }
+            return;
+        }
+
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        match self.circuit {
+            CircuitState::HalfOpen => {
+                warn!("probe failed. circuit is OPEN");
+                self.circuit = CircuitState::Open(Instant::now());
+            }
+            CircuitState::Closed if self.consecutive_failures >= self.config.failure_threshold => {
+                warn!(
+                    "circuit is OPEN after {} consecutive failures",
+                    self.consecutive_failures
+                );
+                self.circuit = CircuitState::Open(Instant::now());
+            }
+            _ => {}
+        }
+    }
+}
+
 // TODO: ここらへんのunwrap削減するぞ！
 #[instrument(name = "remote", skip_all, fields(name = target.name, bucket = target.s3.bucket))]
-pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<()>) -> S3Remote {
+pub fn spawn_remote(
+    bucket: String,
+    target: S3Target,
+    setup: &S3ReproxySetup,
+    set: &mut JoinSet<()>,
+) -> S3Remote {
     let s3_config = aws_sdk_s3::config::Builder::new()
         .endpoint_url(target.s3.endpoint)
         .credentials_provider(Credentials::new(
@@ -160,14 +521,31 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
     info!("Created new remote client.");
 
     let (tx, mut rx) = mpsc::channel(32);
+    let (status, status_rx) = watch::channel(RemoteStatus::default());
+    let mut health = RemoteHealth::new(status, target.circuit_breaker.clone());
+    let health_check_interval = *setup.args.health_check_interval;
 
     set.spawn(
         async move {
-            let mut health: Option<bool> = None;
+            let mut health_check = tokio::time::interval(health_check_interval);
 
             loop {
                 tokio::select! {
+                    _ = health_check.tick() => {
+                        // While the circuit is open, the check waits for its turn as the half-open probe.
+                        if health.admit() {
+                            health.begin();
+                            let q = client.head_bucket().bucket(target.s3.bucket.clone()).send().await;
+                            if let Some(Err(e)) = map_health(&mut health, q) {
+                                warn!("Health check failed: {:?}", e);
+                            }
+                        }
+                    }
                     Some(msg) = rx.recv() => match msg {
+                        msg if msg.is_cancelled() => {
+                            info!("Request cancelled. skipping");
+                        }
+                        msg if !health.allows(&msg) => msg.reject(),
                         RemoteMessage::HealthCheck { reply } => {
                             info!("Checking health...");
                             let q = client.head_bucket().bucket(target.s3.bucket.clone()).send().await;
@@ -192,7 +570,23 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
                                 .await;
                             let _ = reply.send(map_health(&mut health, q));
                         }
-                        RemoteMessage::GetObject { input, reply } => {
+                        RemoteMessage::ListObjectVersions { input, reply } => {
+                            info!("Listing object versions...");
+                            let q = client.list_object_versions()
+                                .bucket(target.s3.bucket.clone())
+                                .set_delimiter(input.delimiter)
+                                .set_encoding_type(input.encoding_type)
+                                .set_key_marker(input.key_marker)
+                                .set_max_keys(input.max_keys)
+                                .set_prefix(input.prefix)
+                                .set_version_id_marker(input.version_id_marker)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .set_request_payer(input.request_payer)
+                                .send()
+                                .await;
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
+                        RemoteMessage::GetObject { input, mut reply } => {
                             info!("Get object...");
 
                             let q = client.get_object()
@@ -217,8 +611,16 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
                                 .set_sse_customer_key(input.sse_customer_key)
                                 .set_sse_customer_key_md5(input.sse_customer_key_md5)
                                 .set_version_id(input.version_id)
-                                .send()
-                                .await;
+                                .send();
+                            // A hedged read is dropped once another remote answered, so the remote is free for the next request.
+                            let q = tokio::select! {
+                                q = q => q,
+                                _ = reply.closed() => {
+                                    info!("Request dropped. aborting");
+                                    health.abandon();
+                                    continue;
+                                }
+                            };
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
@@ -267,6 +669,54 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
+                        RemoteMessage::CopyObject { input, source_key, source_version_id, reply } => {
+                            info!("Copy object...");
+                            let q = client.copy_object()
+                                .bucket(target.s3.bucket.clone())
+                                .copy_source(copy_source(&target.s3.bucket, &source_key, source_version_id.as_deref()))
+                                .set_acl(input.acl)
+                                .set_cache_control(input.cache_control)
+                                .set_checksum_algorithm(input.checksum_algorithm)
+                                .set_content_disposition(input.content_disposition)
+                                .set_content_encoding(input.content_encoding)
+                                .set_content_language(input.content_language)
+                                .set_content_type(input.content_type)
+                                .set_copy_source_if_match(input.copy_source_if_match)
+                                .set_copy_source_if_modified_since(input.copy_source_if_modified_since)
+                                .set_copy_source_if_none_match(input.copy_source_if_none_match)
+                                .set_copy_source_if_unmodified_since(input.copy_source_if_unmodified_since)
+                                .set_expires(input.expires)
+                                .set_grant_full_control(input.grant_full_control)
+                                .set_grant_read(input.grant_read)
+                                .set_grant_read_acp(input.grant_read_acp)
+                                .set_grant_write_acp(input.grant_write_acp)
+                                .set_key(input.key)
+                                .set_metadata(input.metadata)
+                                .set_metadata_directive(input.metadata_directive)
+                                .set_tagging_directive(input.tagging_directive)
+                                .set_server_side_encryption(input.server_side_encryption)
+                                .set_storage_class(input.storage_class)
+                                .set_website_redirect_location(input.website_redirect_location)
+                                .set_sse_customer_algorithm(input.sse_customer_algorithm)
+                                .set_sse_customer_key(input.sse_customer_key)
+                                .set_sse_customer_key_md5(input.sse_customer_key_md5)
+                                .set_ssekms_key_id(input.ssekms_key_id)
+                                .set_ssekms_encryption_context(input.ssekms_encryption_context)
+                                .set_bucket_key_enabled(input.bucket_key_enabled)
+                                .set_copy_source_sse_customer_algorithm(input.copy_source_sse_customer_algorithm)
+                                .set_copy_source_sse_customer_key(input.copy_source_sse_customer_key)
+                                .set_copy_source_sse_customer_key_md5(input.copy_source_sse_customer_key_md5)
+                                .set_request_payer(input.request_payer)
+                                .set_tagging(input.tagging)
+                                .set_object_lock_mode(input.object_lock_mode)
+                                .set_object_lock_retain_until_date(input.object_lock_retain_until_date)
+                                .set_object_lock_legal_hold_status(input.object_lock_legal_hold_status)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
                         RemoteMessage::DeleteObject { input, reply } => {
                             info!("Delete object...");
                             let q = client.delete_object()
@@ -297,7 +747,7 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
-                        RemoteMessage::HeadObject { input, reply } => {
+                        RemoteMessage::HeadObject { input, mut reply } => {
                             info!("Head object...");
                             let q = client.head_object()
                                 .bucket(target.s3.bucket.clone())
@@ -320,8 +770,15 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
                                 .set_part_number(input.part_number)
                                 .set_expected_bucket_owner(input.expected_bucket_owner)
                                 .set_checksum_mode(input.checksum_mode)
-                                .send()
-                                .await;
+                                .send();
+                            let q = tokio::select! {
+                                q = q => q,
+                                _ = reply.closed() => {
+                                    info!("Request dropped. aborting");
+                                    health.abandon();
+                                    continue;
+                                }
+                            };
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
@@ -392,6 +849,35 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
+                        RemoteMessage::UploadPartCopy { input, source_key, source_version_id, reply } => {
+                            let span = info_span!("upload_part_copy_message", part_number = &input.part_number);
+                            let _guard = span.enter();
+                            info!("Upload part copy...");
+
+                            let q = client.upload_part_copy()
+                                .bucket(target.s3.bucket.clone())
+                                .copy_source(copy_source(&target.s3.bucket, &source_key, source_version_id.as_deref()))
+                                .set_copy_source_if_match(input.copy_source_if_match)
+                                .set_copy_source_if_modified_since(input.copy_source_if_modified_since)
+                                .set_copy_source_if_none_match(input.copy_source_if_none_match)
+                                .set_copy_source_if_unmodified_since(input.copy_source_if_unmodified_since)
+                                .set_copy_source_range(input.copy_source_range)
+                                .set_key(input.key)
+                                .set_part_number(input.part_number)
+                                .set_upload_id(input.upload_id)
+                                .set_sse_customer_algorithm(input.sse_customer_algorithm)
+                                .set_sse_customer_key(input.sse_customer_key)
+                                .set_sse_customer_key_md5(input.sse_customer_key_md5)
+                                .set_copy_source_sse_customer_algorithm(input.copy_source_sse_customer_algorithm)
+                                .set_copy_source_sse_customer_key(input.copy_source_sse_customer_key)
+                                .set_copy_source_sse_customer_key_md5(input.copy_source_sse_customer_key_md5)
+                                .set_request_payer(input.request_payer)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
                         RemoteMessage::CompleteMultiPartUpload { input, reply } => {
                             info!("Complete multipart upload...");
 
@@ -414,6 +900,82 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
 
                             let _ = reply.send(map_health(&mut health, q));
                         }
+                        RemoteMessage::AbortMultiPartUpload { input, reply } => {
+                            info!("Abort multipart upload...");
+
+                            let q = client.abort_multipart_upload()
+                                .bucket(target.s3.bucket.clone())
+                                .set_key(input.key)
+                                .set_upload_id(input.upload_id)
+                                .set_request_payer(input.request_payer)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
+                        RemoteMessage::GetObjectTagging { input, mut reply } => {
+                            info!("Get object tagging...");
+                            let q = client.get_object_tagging()
+                                .bucket(target.s3.bucket.clone())
+                                .set_key(input.key)
+                                .set_version_id(input.version_id)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .set_request_payer(input.request_payer)
+                                .send();
+                            let q = tokio::select! {
+                                q = q => q,
+                                _ = reply.closed() => {
+                                    info!("Request dropped. aborting");
+                                    health.abandon();
+                                    continue;
+                                }
+                            };
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
+                        RemoteMessage::PutObjectTagging { input, reply } => {
+                            info!("Put object tagging...");
+                            let q = client.put_object_tagging()
+                                .bucket(target.s3.bucket.clone())
+                                .set_key(input.key)
+                                .set_version_id(input.version_id)
+                                .set_tagging(input.tagging)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .set_request_payer(input.request_payer)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
+                        RemoteMessage::DeleteObjectTagging { input, reply } => {
+                            info!("Delete object tagging...");
+                            let q = client.delete_object_tagging()
+                                .bucket(target.s3.bucket.clone())
+                                .set_key(input.key)
+                                .set_version_id(input.version_id)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
+                        RemoteMessage::ListParts { input, reply } => {
+                            info!("List parts...");
+
+                            let q = client.list_parts()
+                                .bucket(target.s3.bucket.clone())
+                                .set_key(input.key)
+                                .set_max_parts(input.max_parts)
+                                .set_part_number_marker(input.part_number_marker)
+                                .set_upload_id(input.upload_id)
+                                .set_request_payer(input.request_payer)
+                                .set_expected_bucket_owner(input.expected_bucket_owner)
+                                .send()
+                                .await;
+
+                            let _ = reply.send(map_health(&mut health, q));
+                        }
                         RemoteMessage::Shutdown => {
                             break;
                         }
@@ -427,15 +989,39 @@ pub fn spawn_remote(target: S3Target, setup: &S3ReproxySetup, set: &mut JoinSet<
     );
     S3Remote {
         name: target.name,
+        bucket,
         priority: target.priority,
         read_request: target.read_request,
+        required: target.required,
         tx,
+        backfilling: AtomicBool::new(target.backfill),
+        status: status_rx,
+    }
+}
+
+/// Characters left unescaped in the key part of `x-amz-copy-source`.
+const COPY_SOURCE_KEY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+fn copy_source(bucket: &str, key: &str, version_id: Option<&str>) -> String {
+    let source = format!("{}/{}", bucket, utf8_percent_encode(key, COPY_SOURCE_KEY));
+    match version_id {
+        Some(version_id) => format!(
+            "{}?versionId={}",
+            source,
+            utf8_percent_encode(version_id, NON_ALPHANUMERIC)
+        ),
+        None => source,
     }
 }
 
 #[instrument(name = "remote/health", skip_all)]
 fn map_health<T, E1: Debug, E2: Debug>(
-    self_health: &mut Option<bool>,
+    self_health: &mut RemoteHealth,
     query: Result<T, SdkError<E1, E2>>,
 ) -> Option<Result<T, ServiceError<E1, E2>>> {
     // ServiceErrorはリモートが返してきたエラーなので, DOWNとは判断しない
@@ -447,13 +1033,78 @@ fn map_health<T, E1: Debug, E2: Debug>(
             (None, false)
         }
     };
-    if *self_health != Some(health) {
-        if health {
-            info!("remote is UP")
-        } else {
-            warn!("remote is DOWN")
+    let latency = self_health
+        .started_at
+        .take()
+        .map(|started_at| started_at.elapsed());
+    self_health.record(health);
+    self_health.status.send_modify(|status| {
+        if status.health != Some(health) {
+            if health {
+                info!("remote is UP")
+            } else {
+                warn!("remote is DOWN")
+            }
+            status.health = Some(health);
         }
-        *self_health = Some(health);
-    }
+        status.record(health, latency);
+    });
     query
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn health(open_duration: &str) -> RemoteHealth {
+        let (status, _) = watch::channel(RemoteStatus::default());
+        RemoteHealth::new(
+            status,
+            CircuitBreakerConfig {
+                failure_threshold: 3,
+                open_duration: open_duration.parse().unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures() {
+        let mut health = health("1h");
+
+        health.record(false);
+        health.record(false);
+        health.record(true);
+        health.record(false);
+        health.record(false);
+        assert_eq!(health.circuit, CircuitState::Closed);
+        assert!(health.admit());
+
+        health.record(false);
+        assert!(matches!(health.circuit, CircuitState::Open(_)));
+        assert!(!health.admit());
+        assert!(!health.allows(&RemoteMessage::HealthCheck {
+            reply: oneshot::channel().0,
+        }));
+        assert!(health.allows(&RemoteMessage::Shutdown));
+    }
+
+    #[test]
+    fn half_open_probe_decides() {
+        let mut health = health("0s");
+        for _ in 0..3 {
+            health.record(false);
+        }
+
+        assert!(health.admit());
+        assert_eq!(health.circuit, CircuitState::HalfOpen);
+        health.record(false);
+        assert!(matches!(health.circuit, CircuitState::Open(_)));
+
+        assert!(health.admit());
+        assert_eq!(health.circuit, CircuitState::HalfOpen);
+        health.record(true);
+        assert_eq!(health.circuit, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+    }
+}
@@ -0,0 +1,194 @@
+use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectInput};
+use aws_sdk_s3::operation::put_object::{PutObjectError, PutObjectInput, PutObjectOutput};
+use aws_sdk_s3::operation::upload_part::UploadPartInput;
+use aws_sdk_s3::operation::upload_part_copy::{
+    UploadPartCopyError, UploadPartCopyInput, UploadPartCopyOutput,
+};
+use aws_sdk_s3::types::CopyPartResult;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::ServiceError;
+use futures::StreamExt;
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tracing::{error, info, instrument, warn};
+
+use super::clone::UploadPartInputMultiplier;
+use super::remote::{RemoteMessage, S3Remote};
+
+#[derive(Error, Debug)]
+pub enum ReplicateError {
+    #[error("remote({0:?}) is unavailable")]
+    Unavailable(String),
+
+    #[error("failed to get object from source: {0:?}")]
+    GetObject(ServiceError<GetObjectError, HttpResponse>),
+
+    #[error("failed to put object into target: {0:?}")]
+    PutObject(ServiceError<PutObjectError, HttpResponse>),
+}
+
+/// Streams `key` from `source` and puts it into `target`, keeping the headers and user metadata.
+#[instrument(skip_all, name = "replicate", fields(key = key, source = source.name, target = target.name))]
+pub async fn replicate_object(
+    source: &S3Remote,
+    target: &S3Remote,
+    key: &str,
+) -> Result<PutObjectOutput, ReplicateError> {
+    let input = GetObjectInput::builder().key(key).build().unwrap();
+
+    let Some(object) = (try {
+        let (tx, rx) = oneshot::channel();
+        source
+            .tx
+            .send(RemoteMessage::GetObject { input, reply: tx })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        return Err(ReplicateError::Unavailable(source.name.clone()));
+    };
+    let object = object.map_err(ReplicateError::GetObject)?;
+
+    let input = PutObjectInput::builder()
+        .key(key)
+        .body(object.body)
+        .set_content_length(object.content_length)
+        .set_content_type(object.content_type)
+        .set_content_encoding(object.content_encoding)
+        .set_content_disposition(object.content_disposition)
+        .set_content_language(object.content_language)
+        .set_cache_control(object.cache_control)
+        .set_website_redirect_location(object.website_redirect_location)
+        .set_metadata(object.metadata)
+        .build()
+        .unwrap();
+
+    let Some(result) = (try {
+        let (tx, rx) = oneshot::channel();
+        target
+            .tx
+            .send(RemoteMessage::PutObject { input, reply: tx })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        return Err(ReplicateError::Unavailable(target.name.clone()));
+    };
+    let output = result.map_err(ReplicateError::PutObject)?;
+
+    info!("replicated");
+
+    Ok(output)
+}
+
+/// Reads the copy source range from `source` once and uploads it to each of `missing` as an UploadPart.
+/// The original UploadPartCopy error is reported for remotes where this fails too.
+#[allow(clippy::type_complexity)]
+pub async fn upload_part_from_remote(
+    source: &S3Remote,
+    input: &UploadPartCopyInput,
+    source_key: String,
+    source_version_id: Option<String>,
+    missing: Vec<(
+        &S3Remote,
+        String,
+        ServiceError<UploadPartCopyError, HttpResponse>,
+    )>,
+) -> Vec<(
+    String,
+    Result<UploadPartCopyOutput, ServiceError<UploadPartCopyError, HttpResponse>>,
+)> {
+    warn!(
+        "{} remote(s) do not have the source range. reading from remote({:?})",
+        missing.len(),
+        source.name
+    );
+
+    let get = GetObjectInput::builder()
+        .key(source_key)
+        .set_version_id(source_version_id)
+        .set_range(input.copy_source_range.clone())
+        .build()
+        .unwrap();
+
+    let Some(Ok(object)) = (try {
+        let (tx, rx) = oneshot::channel();
+        source
+            .tx
+            .send(RemoteMessage::GetObject {
+                input: get,
+                reply: tx,
+            })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        error!(
+            "failed to read the source range from remote({:?})",
+            source.name
+        );
+        return missing
+            .into_iter()
+            .map(|(remote, _, e)| (remote.name.clone(), Err(e)))
+            .collect();
+    };
+
+    let part = UploadPartInput::builder()
+        .body(object.body)
+        .set_content_length(object.content_length)
+        .set_key(input.key.clone())
+        .set_part_number(input.part_number)
+        .build()
+        .unwrap();
+
+    let (mut input_multiplier, signal) = UploadPartInputMultiplier::from_input(part);
+    let remotes = futures::stream::iter(missing.into_iter())
+        .map(|(remote, upload_id, e)| {
+            let input = input_multiplier.input();
+            async move {
+                let mut input = input.await.unwrap();
+                input.upload_id = Some(upload_id);
+                (remote, input, e)
+            }
+        })
+        .boxed()
+        .buffer_unordered(8)
+        .collect::<Vec<_>>()
+        .await;
+
+    input_multiplier.close();
+    signal.await.unwrap();
+
+    futures::stream::iter(remotes.into_iter())
+        .map(|(remote, input, e)| async move {
+            let Some(result) = (try {
+                let (tx, rx) = oneshot::channel();
+                remote
+                    .tx
+                    .send(RemoteMessage::UploadPart { input, reply: tx })
+                    .await
+                    .ok()?;
+                rx.await.ok()??
+            }) else {
+                warn!("remote({:?}) request failed. skipping", remote.name);
+                return (remote.name.clone(), Err(e));
+            };
+            let result = match result {
+                Ok(output) => Ok(UploadPartCopyOutput::builder()
+                    .copy_part_result(CopyPartResult::builder().set_e_tag(output.e_tag).build())
+                    .build()),
+                Err(upload_err) => {
+                    error!(
+                        "remote({:?}) upload part failed: {:?}",
+                        remote.name, upload_err
+                    );
+                    Err(e)
+                }
+            };
+            (remote.name.clone(), result)
+        })
+        .boxed()
+        .buffer_unordered(8)
+        .collect::<Vec<_>>()
+        .await
+}
@@ -1,4 +1,7 @@
-use aws_sdk_s3::primitives::ByteStream;
+use std::sync::Arc;
+
+use aws_sdk_s3::operation::get_object::{GetObjectInput, GetObjectOutput};
+use aws_sdk_s3::primitives::{ByteStream, DateTime};
 use bytes::Bytes;
 use http_body::{Body, SizeHint};
 use pin_project::pin_project;
@@ -6,6 +9,8 @@ use thiserror::Error;
 use tokio::sync::{mpsc, oneshot, watch};
 use tracing::{error, info, info_span, instrument, warn, Instrument};
 
+use super::remote::{RemoteMessage, S3Remote};
+
 // TODO: unwrap 多すぎ……
 
 //https://docs.rs/aws-sdk-s3/latest/aws_sdk_s3/primitives/struct.SdkBody.html#method.from_body_1_x
@@ -128,6 +133,211 @@ impl ByteStreamMultiplier {
     }
 }
 
+/// Wraps a GetObject body so that when it fails midway, the remaining bytes are read from the `fallbacks` remotes in order.
+/// The ranged GET is guarded by a `ResumeGuard`, so only the same object is spliced in.
+pub(crate) fn failover_bytestream(
+    mut stream: ByteStream,
+    remotes: Arc<Vec<S3Remote>>,
+    fallbacks: Vec<String>,
+    input: GetObjectInput,
+    e_tag: String,
+    content_range: Option<&str>,
+    content_length: Option<i64>,
+) -> ByteStream {
+    let content_length = content_length.and_then(|l| u64::try_from(l).ok());
+    // Without `Content-Range`, the whole object is served.
+    let start = content_range.and_then(parse_content_range).unwrap_or(0);
+    let end = content_length.filter(|l| *l > 0).map(|l| start + l - 1);
+    let total = match content_range {
+        Some(content_range) => parse_content_range_total(content_range),
+        None => content_length,
+    };
+    let guard = ResumeGuard::new(e_tag, total, DateTime::from(std::time::SystemTime::now()));
+
+    let (frame_tx, frame_rx) = mpsc::channel(16);
+    let mut size_hint = SizeHint::default();
+    if let Some(content_length) = content_length {
+        size_hint.set_exact(content_length);
+    }
+    let (_, size_hint_rx) = watch::channel(size_hint);
+
+    tokio::spawn(
+        async move {
+            let mut fallbacks = fallbacks.into_iter();
+            let mut received = 0;
+
+            loop {
+                let payload = match stream.next().await {
+                    Some(Ok(data)) => {
+                        received += data.len() as u64;
+                        Some(Ok(data))
+                    }
+                    Some(Err(e)) => {
+                        warn!("stream failed after {} bytes: {}", received, e);
+                        match resume(
+                            &remotes,
+                            &mut fallbacks,
+                            &input,
+                            &guard,
+                            start + received,
+                            end,
+                        )
+                        .await
+                        {
+                            Some(resumed) => {
+                                stream = resumed;
+                                continue;
+                            }
+                            None => Some(Err(ByteStreamError::ByteStreamError(e.to_string()))),
+                        }
+                    }
+                    None => None,
+                };
+
+                let end_of_stream = !matches!(payload, Some(Ok(_)));
+                if frame_tx.send(payload).await.is_err() {
+                    info!("receiver dropped");
+                    break;
+                }
+                if end_of_stream {
+                    break;
+                }
+            }
+
+            info!("failover stream ended");
+        }
+        .instrument(info_span!("failover_stream")),
+    );
+
+    ByteStream::from_body_1_x(ByteStreamReceiver {
+        frame_rx,
+        size_hint_rx,
+        is_end_stream_reached: false,
+        part_number: None,
+    })
+}
+
+/// Start offset of `bytes <start>-<end>/<total>`.
+fn parse_content_range(content_range: &str) -> Option<u64> {
+    let range = content_range.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.parse().ok()
+}
+
+/// Total size of `bytes <start>-<end>/<total>`, unless it is `*`.
+fn parse_content_range_total(content_range: &str) -> Option<u64> {
+    let (_, total) = content_range.rsplit_once('/')?;
+    total.parse().ok()
+}
+
+/// How a fallback remote's copy is told to be the same object as the one whose body failed.
+#[derive(Debug, PartialEq)]
+enum ResumeGuard {
+    /// Single-part ETags are the MD5 of the content, so they are the same on every remote holding the object.
+    ETag(String),
+    /// Multipart ETags depend on the parts, and copies made by repairs are uploaded in a single part, so they never match.
+    /// Such copies are matched on their size instead, and must not have been modified since the body started.
+    /// `Last-Modified` has a precision of a second, so an overwrite of the same size within that second goes unnoticed.
+    Unmodified { total: Option<u64>, since: DateTime },
+}
+
+impl ResumeGuard {
+    fn new(e_tag: String, total: Option<u64>, started_at: DateTime) -> Self {
+        if e_tag.contains('-') {
+            Self::Unmodified {
+                total,
+                since: started_at,
+            }
+        } else {
+            Self::ETag(e_tag)
+        }
+    }
+
+    fn apply(&self, input: &mut GetObjectInput) {
+        input.if_none_match = None;
+        input.if_modified_since = None;
+        match self {
+            Self::ETag(e_tag) => {
+                input.if_match = Some(e_tag.clone());
+                input.if_unmodified_since = None;
+            }
+            Self::Unmodified { since, .. } => {
+                input.if_match = None;
+                input.if_unmodified_since = Some(*since);
+            }
+        }
+    }
+
+    /// Whether the response of the ranged GET is for the same object.
+    /// Remotes check the preconditions themselves; only the size is left to compare.
+    fn accepts(&self, output: &GetObjectOutput) -> bool {
+        match self {
+            Self::ETag(_) => true,
+            Self::Unmodified { total, .. } => {
+                total.is_some()
+                    && output
+                        .content_range
+                        .as_deref()
+                        .and_then(parse_content_range_total)
+                        == *total
+            }
+        }
+    }
+}
+
+/// Requests bytes `from..=end` of the object from the next fallback remote which still has it.
+async fn resume(
+    remotes: &[S3Remote],
+    fallbacks: &mut impl Iterator<Item = String>,
+    input: &GetObjectInput,
+    guard: &ResumeGuard,
+    from: u64,
+    end: Option<u64>,
+) -> Option<ByteStream> {
+    for name in fallbacks {
+        let Some(remote) = remotes.iter().find(|r| r.name == name) else {
+            continue;
+        };
+
+        let mut input = input.clone();
+        input.range = Some(match end {
+            Some(end) => format!("bytes={}-{}", from, end),
+            None => format!("bytes={}-", from),
+        });
+        input.part_number = None;
+        guard.apply(&mut input);
+
+        let Some(result) = (try {
+            let (tx, rx) = oneshot::channel();
+            remote
+                .tx
+                .send(RemoteMessage::GetObject { input, reply: tx })
+                .await
+                .ok()?;
+            rx.await.ok()??
+        }) else {
+            warn!("remote({:?}) request failed. skipping", name);
+            continue;
+        };
+
+        match result {
+            Ok(output) if !guard.accepts(&output) => {
+                warn!(
+                    "remote({:?}) has an object of another size (content range: {:?})",
+                    name, output.content_range
+                );
+            }
+            Ok(output) => {
+                info!("resuming from remote({:?}) at byte {}", name, from);
+                return Some(output.body);
+            }
+            Err(e) => warn!("remote({:?}) cannot resume: {:?}", name, e),
+        }
+    }
+
+    None
+}
+
 fn convert_sizehint(bound: (u64, Option<u64>)) -> SizeHint {
     let mut size_hint = SizeHint::default();
     size_hint.set_lower(bound.0);
@@ -187,3 +397,50 @@ impl Body for ByteStreamReceiver {
         self.size_hint_rx.borrow().clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn ranged(content_range: &str) -> GetObjectOutput {
+        GetObjectOutput::builder()
+            .content_range(content_range)
+            .build()
+    }
+
+    #[test]
+    fn single_part_etag_is_matched() {
+        let started_at = DateTime::from_secs(1_700_000_000);
+        let guard = ResumeGuard::new("\"abc\"".to_string(), Some(10), started_at);
+        assert_eq!(guard, ResumeGuard::ETag("\"abc\"".to_string()));
+
+        let mut input = GetObjectInput::builder().key("key").build().unwrap();
+        guard.apply(&mut input);
+        assert_eq!(input.if_match.as_deref(), Some("\"abc\""));
+        assert_eq!(input.if_unmodified_since, None);
+    }
+
+    #[test]
+    fn multipart_etag_is_matched_on_size_and_modification_time() {
+        let started_at = DateTime::from_secs(1_700_000_000);
+        // A repaired copy of a multipart object has a single-part ETag, so the ETag is not sent.
+        let guard = ResumeGuard::new("\"abc-2\"".to_string(), Some(10), started_at);
+
+        let mut input = GetObjectInput::builder().key("key").build().unwrap();
+        guard.apply(&mut input);
+        assert_eq!(input.if_match, None);
+        assert_eq!(input.if_unmodified_since, Some(started_at));
+
+        assert!(guard.accepts(&ranged("bytes 4-9/10")));
+        assert!(!guard.accepts(&ranged("bytes 4-11/12")));
+        assert!(!ResumeGuard::new("\"abc-2\"".to_string(), None, started_at)
+            .accepts(&ranged("bytes 4-9/10")));
+    }
+
+    #[test]
+    fn content_range_total() {
+        assert_eq!(parse_content_range_total("bytes 0-9/10"), Some(10));
+        assert_eq!(parse_content_range_total("bytes 0-9/*"), None);
+    }
+}
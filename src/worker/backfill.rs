@@ -0,0 +1,391 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_s3::operation::head_object::{HeadObjectError, HeadObjectInput};
+use aws_sdk_s3::operation::list_objects_v2::{ListObjectsV2Error, ListObjectsV2Output};
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::ServiceError;
+use futures::StreamExt;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use thiserror::Error;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn, Instrument};
+
+use crate::config::s3_target::{rule_for, Config, RoutingRule};
+use crate::config::S3ReproxySetup;
+use crate::db::{BackfillCheckpoint, MongoDB};
+use crate::server::remote::{RemoteMessage, S3Remote};
+use crate::server::replicate::{replicate_object, ReplicateError};
+
+const BACKFILL_PAGE_SIZE: i32 = 1000;
+const BACKFILL_CONCURRENCY: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum BackfillError {
+    #[error("remote({0:?}) is not configured")]
+    UnknownRemote(String),
+
+    #[error("no remote is available to backfill from")]
+    NoSource,
+
+    #[error("remote({0:?}) is unavailable")]
+    Unavailable(String),
+
+    #[error("failed to list objects on source: {0:?}")]
+    List(ServiceError<ListObjectsV2Error, HttpResponse>),
+
+    #[error("failed to head object on target: {0:?}")]
+    HeadObject(ServiceError<HeadObjectError, HttpResponse>),
+
+    #[error("{0}")]
+    Replicate(#[from] ReplicateError),
+
+    #[error("mongodb error: {0:?}")]
+    DB(#[from] mongodb::error::Error),
+}
+
+/// Spawns a backfill for every remote which has `backfill` set.
+pub(crate) fn spawn_backfill(
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    setup: &S3ReproxySetup,
+    shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    let retry_interval = *setup.args.backfill_retry_interval;
+
+    for bucket in setup.config.buckets() {
+        for target in bucket.remotes.iter().filter(|t| t.backfill) {
+            spawn_remote_backfill(
+                target.name.clone(),
+                retry_interval,
+                bucket.policy.rules.clone(),
+                Arc::clone(&remotes),
+                Arc::clone(&db),
+                shutdown.clone(),
+                set,
+            );
+        }
+    }
+}
+
+#[instrument(name = "backfill", skip_all, fields(name = name))]
+fn spawn_remote_backfill(
+    name: String,
+    retry_interval: Duration,
+    rules: Vec<RoutingRule>,
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    mut shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    info!("Backfill worker started.");
+
+    set.spawn(
+        async move {
+            let mut ticker = tokio::time::interval(retry_interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        let Some(target) = remotes.iter().find(|r| r.name == name) else {
+                            error!("remote({:?}) is not configured", name);
+                            break;
+                        };
+                        tokio::select! {
+                            result = backfill(&remotes, &db, target, &rules) => match result {
+                                Ok(()) => break,
+                                Err(e) => warn!("Backfill failed: {}. resuming in {:?}", e, retry_interval),
+                            },
+                            _ = shutdown.changed() => break,
+                        }
+                    }
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            info!("Backfill worker shutting down.");
+        }
+        .in_current_span(),
+    );
+}
+
+/// Backfills the remote named `name` once, for the `backfill` subcommand.
+#[instrument(name = "backfill", skip_all, fields(name = name))]
+pub async fn run_backfill(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    config: &Config,
+    name: &str,
+    restart: bool,
+) -> Result<(), BackfillError> {
+    let target = remotes
+        .iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| BackfillError::UnknownRemote(name.to_owned()))?;
+
+    if restart {
+        info!("Discarding the previous checkpoint.");
+        db.backfill_checkpoints
+            .delete_one(doc! { "remote": name })
+            .await?;
+    }
+
+    let rules = config
+        .buckets()
+        .into_iter()
+        .find(|b| b.name == target.bucket)
+        .map(|b| b.policy.rules)
+        .unwrap_or_default();
+
+    target.set_backfilling(true);
+    backfill(remotes, db, target, &rules).await
+}
+
+/// Copies every object from the other remotes of the same bucket into `target`, page by page.
+/// Progress is checkpointed after each page, so an interrupted backfill resumes where it stopped.
+/// Keys which routing rules do not write to `target` are skipped.
+async fn backfill(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    target: &S3Remote,
+    rules: &[RoutingRule],
+) -> Result<(), BackfillError> {
+    let checkpoint = db
+        .backfill_checkpoints
+        .find_one(doc! { "remote": &target.name })
+        .await?;
+
+    let checkpoint = match checkpoint {
+        Some(checkpoint) if checkpoint.completed_at.is_some() => {
+            info!("Backfill has already been completed.");
+            target.set_backfilling(false);
+            return Ok(());
+        }
+        Some(checkpoint) => checkpoint,
+        None => {
+            let now = mongodb::bson::DateTime::now();
+            let checkpoint = BackfillCheckpoint {
+                id: None,
+                remote: target.name.clone(),
+                source: None,
+                start_after: None,
+                completed_sources: vec![],
+                copied: 0,
+                started_at: now,
+                updated_at: now,
+                completed_at: None,
+            };
+            db.backfill_checkpoints.insert_one(&checkpoint).await?;
+            checkpoint
+        }
+    };
+
+    let mut copied = checkpoint.copied;
+
+    for source in sources(remotes, target, rules)? {
+        if checkpoint.completed_sources.contains(&source.name) {
+            continue;
+        }
+
+        let mut start_after = checkpoint
+            .start_after
+            .clone()
+            .filter(|_| checkpoint.source.as_ref() == Some(&source.name));
+
+        info!(
+            "Backfilling from remote({:?}) (start after: {:?}, copied: {}).",
+            source.name, start_after, copied
+        );
+
+        loop {
+            let output = list_objects(source, start_after.clone()).await?;
+            let keys = output
+                .contents()
+                .iter()
+                .filter_map(|o| o.key())
+                .map(str::to_owned)
+                .collect_vec();
+
+            let results = futures::stream::iter(keys.iter())
+                .filter(|key| {
+                    let receives = rule_for(rules, key).map_or(true, |r| r.writes_to(&target.name));
+                    futures::future::ready(receives)
+                })
+                .map(|key| copy_object(source, target, key))
+                .boxed()
+                .buffer_unordered(BACKFILL_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await;
+
+            // The checkpoint is not advanced past a page with failures, so the whole page is retried.
+            copied += results
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|copied| *copied)
+                .count() as i64;
+
+            if let Some(last) = keys.last() {
+                start_after = Some(last.clone());
+            }
+            let done = !output.is_truncated().unwrap_or(false) || keys.is_empty();
+
+            let update = if done {
+                doc! {
+                    "$set": {
+                        "source": None::<String>,
+                        "start_after": None::<String>,
+                        "copied": copied,
+                        "updated_at": mongodb::bson::DateTime::now(),
+                    },
+                    "$push": {
+                        "completed_sources": &source.name,
+                    },
+                }
+            } else {
+                doc! {
+                    "$set": {
+                        "source": &source.name,
+                        "start_after": start_after.clone(),
+                        "copied": copied,
+                        "updated_at": mongodb::bson::DateTime::now(),
+                    },
+                }
+            };
+            db.backfill_checkpoints
+                .update_one(doc! { "remote": &target.name }, update)
+                .await?;
+
+            info!("{} objects copied (last key: {:?}).", copied, start_after);
+
+            if done {
+                break;
+            }
+        }
+    }
+
+    db.backfill_checkpoints
+        .update_one(
+            doc! { "remote": &target.name },
+            doc! {
+                "$set": {
+                    "completed_at": mongodb::bson::DateTime::now(),
+                },
+            },
+        )
+        .await?;
+
+    target.set_backfilling(false);
+    info!("Backfill completed. remote is now readable.");
+
+    Ok(())
+}
+
+/// Remotes to list, so that every key written to `target` is listed from a remote which has it.
+/// Routing rules may keep keys off some remotes, so a source is picked among the writers of each rule writing to `target`,
+/// preferring remotes which are not DOWN, then read remotes, then higher priority.
+/// A remote whose circuit is open is DOWN as well. If a source fails anyway, the backfill is resumed later with the sources available then.
+fn sources<'a>(
+    remotes: &'a [S3Remote],
+    target: &S3Remote,
+    rules: &[RoutingRule],
+) -> Result<Vec<&'a S3Remote>, BackfillError> {
+    let candidates = remotes
+        .iter()
+        .filter(|r| r.bucket == target.bucket && r.name != target.name && !r.is_backfilling())
+        .sorted_by(|a, b| {
+            a.is_down()
+                .cmp(&b.is_down())
+                .then_with(|| b.read_request.cmp(&a.read_request))
+                .then_with(|| b.priority.cmp(&a.priority))
+        })
+        .collect_vec();
+
+    // Keys matching no rule are written to every remote.
+    let mut sources = vec![*candidates.first().ok_or(BackfillError::NoSource)?];
+
+    for rule in rules.iter().filter(|r| r.writes_to(&target.name)) {
+        let writers = candidates
+            .iter()
+            .filter(|r| rule.writes_to(&r.name))
+            .collect_vec();
+        if writers
+            .iter()
+            .any(|r| !r.is_down() && sources.iter().any(|s| s.name == r.name))
+        {
+            continue;
+        }
+        // A rule writing to `target` alone leaves nothing to copy.
+        if let Some(writer) = writers.first() {
+            if !sources.iter().any(|s| s.name == writer.name) {
+                sources.push(writer);
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+async fn list_objects(
+    source: &S3Remote,
+    start_after: Option<String>,
+) -> Result<ListObjectsV2Output, BackfillError> {
+    let Some(result) = (try {
+        let (tx, rx) = oneshot::channel();
+        source
+            .tx
+            .send(RemoteMessage::ListObjects {
+                prefix: None,
+                delimiter: None,
+                max_keys: Some(BACKFILL_PAGE_SIZE),
+                start_after,
+                reply: tx,
+            })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        return Err(BackfillError::Unavailable(source.name.clone()));
+    };
+
+    result.map_err(BackfillError::List)
+}
+
+/// Copies `key` unless `target` already has it, which means it has been written since the backfill started.
+/// Returns whether the object was copied.
+async fn copy_object(
+    source: &S3Remote,
+    target: &S3Remote,
+    key: &str,
+) -> Result<bool, BackfillError> {
+    let input = HeadObjectInput::builder().key(key).build().unwrap();
+
+    let Some(result) = (try {
+        let (tx, rx) = oneshot::channel();
+        target
+            .tx
+            .send(RemoteMessage::HeadObject { input, reply: tx })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        return Err(BackfillError::Unavailable(target.name.clone()));
+    };
+
+    match result {
+        Ok(_) => return Ok(false),
+        Err(e) if e.err().is_not_found() => {}
+        Err(e) => return Err(BackfillError::HeadObject(e)),
+    }
+
+    match replicate_object(source, target, key).await {
+        Ok(_) => Ok(true),
+        Err(ReplicateError::GetObject(e)) if e.err().is_no_such_key() => {
+            info!("object has been deleted since listing (key: {})", key);
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
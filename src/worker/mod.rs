@@ -0,0 +1,4 @@
+pub mod backfill;
+pub mod multipart_gc;
+pub mod repair;
+pub mod scrub;
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_s3::operation::abort_multipart_upload::AbortMultipartUploadInput;
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn, Instrument};
+
+use crate::config::S3ReproxySetup;
+use crate::db::{MongoDB, MultipartUploadIds, PartUploadStatus, RemoteMultipartUploadId};
+use crate::server::remote::{RemoteMessage, S3Remote};
+
+#[instrument(name = "multipart_gc", skip_all)]
+pub fn spawn_multipart_gc(
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    setup: &S3ReproxySetup,
+    mut shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    let interval = *setup.args.multipart_gc_interval;
+    let max_age = *setup.args.multipart_gc_max_age;
+
+    info!(
+        "Multipart upload garbage collector started (interval: {:?}, max age: {:?}).",
+        interval, max_age
+    );
+
+    set.spawn(
+        async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        if let Err(e) = collect(&remotes, &db, max_age).await {
+                            error!("mongodb error: {:?}", e);
+                        }
+                    }
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            info!("Multipart upload garbage collector shutting down.");
+        }
+        .in_current_span(),
+    );
+}
+
+async fn collect(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    max_age: Duration,
+) -> Result<(), mongodb::error::Error> {
+    let deadline = mongodb::bson::DateTime::from_millis(
+        mongodb::bson::DateTime::now().timestamp_millis() - max_age.as_millis() as i64,
+    );
+
+    let mut stale = db
+        .multipart_upload_ids
+        .find(doc! {
+            "completed_at": None::<mongodb::bson::DateTime>,
+            "aborted_at": None::<mongodb::bson::DateTime>,
+            "created_at": { "$lt": deadline },
+        })
+        .await?;
+
+    while let Some(upload) = stale.next().await {
+        let upload = upload?;
+        let Some(id) = upload.id else {
+            continue;
+        };
+        info!("aborting stale multipart upload (upload_id: {})", id);
+        abort_uploads(remotes, db, id, &upload, |status| {
+            status != &PartUploadStatus::Aborted
+        })
+        .await?;
+        db.multipart_upload_ids
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$set": {
+                        "aborted_at": mongodb::bson::DateTime::now(),
+                    },
+                },
+            )
+            .await?;
+    }
+
+    let mut cancelled = db
+        .multipart_upload_ids
+        .find(doc! {
+            "key": { "$ne": None::<String> },
+            "upload_ids.status": mongodb::bson::to_bson(&PartUploadStatus::Cancelled)?,
+        })
+        .await?;
+
+    while let Some(upload) = cancelled.next().await {
+        let upload = upload?;
+        let Some(id) = upload.id else {
+            continue;
+        };
+        info!("aborting cancelled remote uploads (upload_id: {})", id);
+        abort_uploads(remotes, db, id, &upload, |status| {
+            status == &PartUploadStatus::Cancelled
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Aborts the remote uploads whose status matches `filter`.
+/// Remote uploads that were aborted become `Aborted`, others become `Cancelled` to be retried on the next run.
+async fn abort_uploads(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    id: ObjectId,
+    upload: &MultipartUploadIds,
+    filter: impl Fn(&PartUploadStatus) -> bool,
+) -> Result<(), mongodb::error::Error> {
+    let Some(key) = upload.key.as_deref() else {
+        warn!(
+            "upload_id {} has no key recorded. remote uploads are left as is.",
+            id
+        );
+        return Ok(());
+    };
+
+    for remote_upload in upload.upload_ids.iter().filter(|u| filter(&u.status)) {
+        let Some(remote) = remotes.iter().find(|r| r.name == remote_upload.remote_name) else {
+            warn!(
+                "remote({:?}) is not configured. skipping",
+                remote_upload.remote_name
+            );
+            continue;
+        };
+
+        let status = if abort_remote_upload(remote, key, remote_upload).await {
+            PartUploadStatus::Aborted
+        } else {
+            PartUploadStatus::Cancelled
+        };
+
+        if status == remote_upload.status {
+            continue;
+        }
+
+        db.multipart_upload_ids
+            .update_one(
+                doc! {
+                    "_id": id,
+                    "upload_ids": {
+                        "$elemMatch": {
+                            "remote_name": &remote_upload.remote_name,
+                            "upload_id": &remote_upload.upload_id,
+                        },
+                    },
+                },
+                doc! {
+                    "$set": {
+                        "upload_ids.$.status": mongodb::bson::to_bson(&status)?,
+                    },
+                },
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn abort_remote_upload(
+    remote: &S3Remote,
+    key: &str,
+    remote_upload: &RemoteMultipartUploadId,
+) -> bool {
+    let input = AbortMultipartUploadInput::builder()
+        .key(key)
+        .upload_id(remote_upload.upload_id.clone())
+        .build()
+        .unwrap();
+
+    let Some(result) = (try {
+        let (tx, rx) = oneshot::channel();
+        remote
+            .tx
+            .send(RemoteMessage::AbortMultiPartUpload { input, reply: tx })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        warn!("remote({:?}) request failed. retrying later", remote.name);
+        return false;
+    };
+
+    match result {
+        Ok(_) => {
+            info!("remote({:?}) aborted", remote.name);
+            true
+        }
+        Err(e) if e.err().is_no_such_upload() => {
+            info!(
+                "remote({:?}) has no such upload. treating as aborted",
+                remote.name
+            );
+            true
+        }
+        Err(e) => {
+            warn!("remote({:?}) failed: {:?}", remote.name, e);
+            false
+        }
+    }
+}
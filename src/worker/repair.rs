@@ -0,0 +1,373 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_s3::operation::delete_object::DeleteObjectInput;
+use aws_sdk_s3::operation::delete_object_tagging::DeleteObjectTaggingInput;
+use aws_sdk_s3::operation::get_object_tagging::GetObjectTaggingInput;
+use aws_sdk_s3::operation::head_object::HeadObjectInput;
+use aws_sdk_s3::operation::put_object_tagging::PutObjectTaggingInput;
+use aws_sdk_s3::types::Tagging;
+use futures::StreamExt;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn, Instrument};
+
+use crate::config::S3ReproxySetup;
+use crate::db::{MongoDB, RepairTask};
+use crate::server::remote::{RemoteMessage, S3Remote};
+use crate::server::replicate::{replicate_object, ReplicateError};
+
+const REPAIR_BATCH_SIZE: i64 = 64;
+const REPAIR_MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[instrument(name = "repair", skip_all)]
+pub fn spawn_repair(
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    setup: &S3ReproxySetup,
+    mut shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    let interval = *setup.args.repair_interval;
+
+    info!("Repair worker started (interval: {:?}).", interval);
+
+    set.spawn(
+        async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        if let Err(e) = repair(&remotes, &db, interval).await {
+                            error!("mongodb error: {:?}", e);
+                        }
+                    }
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            info!("Repair worker shutting down.");
+        }
+        .in_current_span(),
+    );
+}
+
+enum Replay {
+    /// The object is not ready to be repaired yet.
+    Postponed,
+    /// Remotes which still disagree with the succeeded remotes.
+    Remaining(Vec<String>),
+}
+
+async fn repair(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    interval: Duration,
+) -> Result<(), mongodb::error::Error> {
+    let mut tasks = db
+        .repair_tasks
+        .find(doc! {
+            "resolved_at": None::<mongodb::bson::DateTime>,
+            "next_attempt_at": { "$lte": mongodb::bson::DateTime::now() },
+        })
+        .sort(doc! { "next_attempt_at": 1 })
+        .limit(REPAIR_BATCH_SIZE)
+        .await?;
+
+    while let Some(task) = tasks.next().await {
+        let task = task?;
+        let Some(id) = task.id else {
+            continue;
+        };
+
+        let now = mongodb::bson::DateTime::now();
+        let update = match replay(remotes, db, &task).await? {
+            Replay::Postponed => {
+                info!("postponed (key: {})", task.key);
+                doc! {
+                    "$set": {
+                        "next_attempt_at": later(now, interval),
+                    },
+                }
+            }
+            Replay::Remaining(remaining) if remaining.is_empty() => {
+                info!("repaired (key: {})", task.key);
+                doc! {
+                    "$set": {
+                        "failed_remotes": Vec::<String>::new(),
+                        "resolved_at": now,
+                    },
+                }
+            }
+            Replay::Remaining(remaining) => {
+                let backoff = interval
+                    .saturating_mul(1 << task.attempts.clamp(0, 16))
+                    .min(REPAIR_MAX_BACKOFF);
+                warn!(
+                    "remote(s) {:?} are still inconsistent (key: {}, attempts: {})",
+                    remaining,
+                    task.key,
+                    task.attempts + 1
+                );
+                doc! {
+                    "$set": {
+                        "failed_remotes": remaining,
+                        "next_attempt_at": later(now, backoff),
+                    },
+                    "$inc": {
+                        "attempts": 1,
+                    },
+                }
+            }
+        };
+
+        db.repair_tasks
+            .update_one(doc! { "_id": id }, update)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn later(now: mongodb::bson::DateTime, after: Duration) -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(now.timestamp_millis() + after.as_millis() as i64)
+}
+
+#[instrument(skip_all, fields(key = task.key, operation = ?task.operation))]
+async fn replay(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    task: &RepairTask,
+) -> Result<Replay, mongodb::error::Error> {
+    if task.operation.is_part() {
+        let upload = match task.upload_id {
+            Some(upload_id) => {
+                db.multipart_upload_ids
+                    .find_one(doc! { "_id": upload_id })
+                    .await?
+            }
+            None => None,
+        };
+        match upload {
+            Some(upload) if upload.completed_at.is_some() => {}
+            Some(upload) if upload.aborted_at.is_none() => return Ok(Replay::Postponed),
+            _ => {
+                info!("multipart upload was not completed. nothing to repair");
+                return Ok(Replay::Remaining(vec![]));
+            }
+        }
+    }
+
+    let mut remaining = vec![];
+    for name in task.failed_remotes.iter() {
+        let Some(target) = remotes.iter().find(|r| &r.name == name) else {
+            warn!("remote({:?}) is not configured anymore. dropping", name);
+            continue;
+        };
+
+        let repaired = if task.operation.is_delete() {
+            delete_unless_rewritten(remotes, task, target).await
+        } else if task.operation.is_tagging() {
+            replicate_tagging_from_succeeded(remotes, task, target).await
+        } else {
+            replicate_from_succeeded(remotes, task, target).await
+        };
+
+        if !repaired {
+            remaining.push(name.clone());
+        }
+    }
+
+    Ok(Replay::Remaining(remaining))
+}
+
+/// Copies the key into `target` from the first succeeded remote that can serve it.
+/// If a succeeded remote no longer has the key, it has been deleted since and is deleted from `target` as well.
+async fn replicate_from_succeeded(
+    remotes: &[S3Remote],
+    task: &RepairTask,
+    target: &S3Remote,
+) -> bool {
+    let sources = remotes
+        .iter()
+        .filter(|r| task.succeeded_remotes.contains(&r.name))
+        .sorted_by(|a, b| b.priority.cmp(&a.priority));
+
+    for source in sources {
+        match replicate_object(source, target, &task.key).await {
+            Ok(_) => return true,
+            Err(ReplicateError::GetObject(e)) if e.err().is_no_such_key() => {
+                info!(
+                    "remote({:?}) no longer has the object. deleting from remote({:?})",
+                    source.name, target.name
+                );
+                return delete_object(target, &task.key).await;
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    false
+}
+
+/// Deletes the key from `target` unless a succeeded remote has it again.
+/// The delete succeeded there, so an object found now has been written since and must not be deleted.
+async fn delete_unless_rewritten(
+    remotes: &[S3Remote],
+    task: &RepairTask,
+    target: &S3Remote,
+) -> bool {
+    let sources = remotes
+        .iter()
+        .filter(|r| task.succeeded_remotes.contains(&r.name));
+
+    for source in sources {
+        let input = HeadObjectInput::builder().key(&task.key).build().unwrap();
+
+        let Some(result) = (try {
+            let (tx, rx) = oneshot::channel();
+            source
+                .tx
+                .send(RemoteMessage::HeadObject { input, reply: tx })
+                .await
+                .ok()?;
+            rx.await.ok()??
+        }) else {
+            warn!("remote({:?}) request failed. retrying later", source.name);
+            return false;
+        };
+
+        match result {
+            Ok(_) => {
+                info!(
+                    "remote({:?}) has the object again. skipping the delete on remote({:?})",
+                    source.name, target.name
+                );
+                return true;
+            }
+            Err(e) if e.err().is_not_found() => {}
+            Err(e) => {
+                warn!("remote({:?}) failed: {:?}", source.name, e);
+                return false;
+            }
+        }
+    }
+
+    delete_object(target, &task.key).await
+}
+
+/// Copies the tag set of the key into `target` from the first succeeded remote that can serve it.
+async fn replicate_tagging_from_succeeded(
+    remotes: &[S3Remote],
+    task: &RepairTask,
+    target: &S3Remote,
+) -> bool {
+    let sources = remotes
+        .iter()
+        .filter(|r| task.succeeded_remotes.contains(&r.name))
+        .sorted_by(|a, b| b.priority.cmp(&a.priority));
+
+    for source in sources {
+        let input = GetObjectTaggingInput::builder()
+            .key(&task.key)
+            .build()
+            .unwrap();
+
+        let Some(result) = (try {
+            let (tx, rx) = oneshot::channel();
+            source
+                .tx
+                .send(RemoteMessage::GetObjectTagging { input, reply: tx })
+                .await
+                .ok()?;
+            rx.await.ok()??
+        }) else {
+            warn!("remote({:?}) request failed. skipping", source.name);
+            continue;
+        };
+
+        match result {
+            Ok(output) => return put_tagging(target, &task.key, output.tag_set).await,
+            Err(e) => warn!("remote({:?}) failed: {:?}", source.name, e),
+        }
+    }
+
+    false
+}
+
+/// Replaces the tag set of the key on `remote`, removing it when `tag_set` is empty.
+async fn put_tagging(remote: &S3Remote, key: &str, tag_set: Vec<aws_sdk_s3::types::Tag>) -> bool {
+    let result: Option<Result<(), String>> = if tag_set.is_empty() {
+        let input = DeleteObjectTaggingInput::builder()
+            .key(key)
+            .build()
+            .unwrap();
+        try {
+            let (tx, rx) = oneshot::channel();
+            remote
+                .tx
+                .send(RemoteMessage::DeleteObjectTagging { input, reply: tx })
+                .await
+                .ok()?;
+            rx.await.ok()??.map(|_| ()).map_err(|e| format!("{:?}", e))
+        }
+    } else {
+        let tagging = Tagging::builder()
+            .set_tag_set(Some(tag_set))
+            .build()
+            .unwrap();
+        let input = PutObjectTaggingInput::builder()
+            .key(key)
+            .tagging(tagging)
+            .build()
+            .unwrap();
+        try {
+            let (tx, rx) = oneshot::channel();
+            remote
+                .tx
+                .send(RemoteMessage::PutObjectTagging { input, reply: tx })
+                .await
+                .ok()?;
+            rx.await.ok()??.map(|_| ()).map_err(|e| format!("{:?}", e))
+        }
+    };
+
+    match result {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            warn!("remote({:?}) failed: {}", remote.name, e);
+            false
+        }
+        None => {
+            warn!("remote({:?}) request failed. retrying later", remote.name);
+            false
+        }
+    }
+}
+
+async fn delete_object(remote: &S3Remote, key: &str) -> bool {
+    let input = DeleteObjectInput::builder().key(key).build().unwrap();
+
+    let Some(result) = (try {
+        let (tx, rx) = oneshot::channel();
+        remote
+            .tx
+            .send(RemoteMessage::DeleteObject { input, reply: tx })
+            .await
+            .ok()?;
+        rx.await.ok()??
+    }) else {
+        warn!("remote({:?}) request failed. retrying later", remote.name);
+        return false;
+    };
+
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("remote({:?}) failed: {:?}", remote.name, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::ServiceError;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use thiserror::Error;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn, Instrument};
+
+use crate::config::s3_target::{rule_for, RoutingRule, ScrubConfig};
+use crate::config::S3ReproxySetup;
+use crate::db::{DiscrepancyKind, MongoDB, ScrubDiscrepancy};
+use crate::server::remote::{RemoteMessage, S3Remote};
+use crate::server::replicate::replicate_object;
+
+const SCRUB_PAGE_SIZE: i32 = 1000;
+
+#[derive(Error, Debug)]
+enum ScrubError {
+    #[error("remote({0:?}) is unavailable")]
+    Unavailable(String),
+
+    #[error("remote({0:?}) failed to list objects: {1:?}")]
+    List(String, ServiceError<ListObjectsV2Error, HttpResponse>),
+
+    #[error("mongodb error: {0:?}")]
+    DB(#[from] mongodb::error::Error),
+}
+
+/// Spawns a scrubber for every remote which has `scrub` configured.
+pub(crate) fn spawn_scrubber(
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    setup: &S3ReproxySetup,
+    shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    for bucket in setup.config.buckets() {
+        for target in &bucket.remotes {
+            let Some(config) = target.scrub.clone() else {
+                continue;
+            };
+            spawn_remote_scrubber(
+                target.name.clone(),
+                config,
+                bucket.policy.rules.clone(),
+                Arc::clone(&remotes),
+                Arc::clone(&db),
+                shutdown.clone(),
+                set,
+            );
+        }
+    }
+}
+
+#[instrument(name = "scrub", skip_all, fields(name = name))]
+fn spawn_remote_scrubber(
+    name: String,
+    config: ScrubConfig,
+    rules: Vec<RoutingRule>,
+    remotes: Arc<Vec<S3Remote>>,
+    db: Arc<MongoDB>,
+    mut shutdown: watch::Receiver<bool>,
+    set: &mut JoinSet<()>,
+) {
+    info!(
+        "Scrubber started (interval: {:?}, pages per second: {}, repair: {}).",
+        *config.interval, config.pages_per_second, config.repair
+    );
+
+    set.spawn(
+        async move {
+            let mut ticker = tokio::time::interval(*config.interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        let Some(target) = remotes.iter().find(|r| r.name == name) else {
+                            error!("remote({:?}) is not configured", name);
+                            break;
+                        };
+                        tokio::select! {
+                            result = scrub(&remotes, &db, target, &config, &rules) => {
+                                if let Err(e) = result {
+                                    error!("Scrub failed: {}", e);
+                                }
+                            }
+                            _ = shutdown.changed() => break,
+                        }
+                    }
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            info!("Scrubber shutting down.");
+        }
+        .in_current_span(),
+    );
+}
+
+struct ListedObject {
+    key: String,
+    e_tag: Option<String>,
+    size: Option<i64>,
+}
+
+/// Pages through the listing of a single remote.
+struct Lister<'a> {
+    remote: &'a S3Remote,
+    buffer: VecDeque<ListedObject>,
+    start_after: Option<String>,
+    done: bool,
+}
+
+impl<'a> Lister<'a> {
+    fn new(remote: &'a S3Remote) -> Self {
+        Lister {
+            remote,
+            buffer: VecDeque::new(),
+            start_after: None,
+            done: false,
+        }
+    }
+
+    fn needs_page(&self) -> bool {
+        self.buffer.is_empty() && !self.done
+    }
+
+    fn peek(&self) -> Option<&ListedObject> {
+        self.buffer.front()
+    }
+
+    fn pop_if(&mut self, key: &str) -> Option<ListedObject> {
+        if self.peek()?.key == key {
+            self.buffer.pop_front()
+        } else {
+            None
+        }
+    }
+
+    async fn fetch(&mut self) -> Result<(), ScrubError> {
+        let Some(result) = (try {
+            let (tx, rx) = oneshot::channel();
+            self.remote
+                .tx
+                .send(RemoteMessage::ListObjects {
+                    prefix: None,
+                    delimiter: None,
+                    max_keys: Some(SCRUB_PAGE_SIZE),
+                    start_after: self.start_after.clone(),
+                    reply: tx,
+                })
+                .await
+                .ok()?;
+            rx.await.ok()??
+        }) else {
+            return Err(ScrubError::Unavailable(self.remote.name.clone()));
+        };
+        let output = result.map_err(|e| ScrubError::List(self.remote.name.clone(), e))?;
+
+        self.buffer.extend(output.contents().iter().filter_map(|o| {
+            Some(ListedObject {
+                key: o.key()?.to_owned(),
+                e_tag: o.e_tag().map(str::to_owned),
+                size: o.size(),
+            })
+        }));
+        if let Some(last) = self.buffer.back() {
+            self.start_after = Some(last.key.clone());
+        }
+        self.done = !output.is_truncated().unwrap_or(false) || self.buffer.is_empty();
+
+        Ok(())
+    }
+}
+
+/// Walks the listings of all remotes of the bucket in key order and records where `target` differs from the reference.
+#[instrument(skip_all)]
+async fn scrub(
+    remotes: &[S3Remote],
+    db: &MongoDB,
+    target: &S3Remote,
+    config: &ScrubConfig,
+    rules: &[RoutingRule],
+) -> Result<(), ScrubError> {
+    info!("Scrubbing...");
+    let started_at = mongodb::bson::DateTime::now();
+
+    let mut listers = remotes
+        .iter()
+        .filter(|r| r.bucket == target.bucket)
+        .map(Lister::new)
+        .collect_vec();
+    let mut limiter =
+        tokio::time::interval(Duration::from_secs(1) / config.pages_per_second.max(1));
+    let (mut scanned, mut found) = (0, 0);
+
+    loop {
+        if listers.iter().any(Lister::needs_page) {
+            limiter.tick().await;
+            futures::future::try_join_all(
+                listers
+                    .iter_mut()
+                    .filter(|l| l.needs_page())
+                    .map(Lister::fetch),
+            )
+            .await?;
+        }
+
+        let Some(key) = listers
+            .iter()
+            .filter_map(Lister::peek)
+            .map(|o| &o.key)
+            .min()
+            .cloned()
+        else {
+            break;
+        };
+
+        let entries = listers
+            .iter_mut()
+            .filter_map(|l| l.pop_if(&key).map(|o| (l.remote, o)))
+            .collect_vec();
+
+        scanned += 1;
+        if let Some(discrepancy) = diff(target, &key, &entries, rules) {
+            found += 1;
+            record(db, remotes, config, discrepancy).await?;
+        }
+    }
+
+    // Anything not detected again in this run has been fixed in the meantime.
+    db.scrub_discrepancies
+        .delete_many(doc! {
+            "remote": &target.name,
+            "repaired_at": None::<mongodb::bson::DateTime>,
+            "detected_at": { "$lt": started_at },
+        })
+        .await?;
+
+    info!(
+        "Scrub finished ({} keys scanned, {} discrepancies).",
+        scanned, found
+    );
+
+    Ok(())
+}
+
+/// Compares `target` against the highest-priority remote that has the key.
+/// Keys which routing rules do not write to `target` are skipped, and only remotes they are written to serve as reference.
+fn diff(
+    target: &S3Remote,
+    key: &str,
+    entries: &[(&S3Remote, ListedObject)],
+    rules: &[RoutingRule],
+) -> Option<ScrubDiscrepancy> {
+    let rule = rule_for(rules, key);
+    if rule.is_some_and(|rule| !rule.writes_to(&target.name)) {
+        return None;
+    }
+
+    let (reference, expected) = entries
+        .iter()
+        .rev()
+        .filter(|(r, _)| !r.is_backfilling())
+        .filter(|(r, _)| rule.map_or(true, |rule| rule.writes_to(&r.name)))
+        .max_by_key(|(r, _)| (r.read_request, r.priority))?;
+
+    if reference.name == target.name {
+        return None;
+    }
+
+    let actual = entries
+        .iter()
+        .find(|(r, _)| r.name == target.name)
+        .map(|(_, o)| o);
+
+    let kind = match actual {
+        None => DiscrepancyKind::Missing,
+        Some(actual) if !same_object(expected, actual) => DiscrepancyKind::Mismatch,
+        Some(_) => return None,
+    };
+
+    warn!(
+        "remote({:?}) differs from remote({:?}) (key: {}, kind: {:?})",
+        target.name, reference.name, key, kind
+    );
+
+    Some(ScrubDiscrepancy {
+        id: None,
+        remote: target.name.clone(),
+        key: key.to_owned(),
+        kind,
+        reference_remote: reference.name.clone(),
+        expected_e_tag: expected.e_tag.clone(),
+        expected_size: expected.size,
+        actual_e_tag: actual.and_then(|o| o.e_tag.clone()),
+        actual_size: actual.and_then(|o| o.size),
+        detected_at: mongodb::bson::DateTime::now(),
+        repaired_at: None,
+    })
+}
+
+/// ETags of multipart objects depend on the part layout, so they are only compared for single-part objects.
+fn same_object(expected: &ListedObject, actual: &ListedObject) -> bool {
+    if expected.size != actual.size {
+        return false;
+    }
+
+    match (&expected.e_tag, &actual.e_tag) {
+        (Some(a), Some(b)) if !a.contains('-') && !b.contains('-') => a == b,
+        _ => true,
+    }
+}
+
+async fn record(
+    db: &MongoDB,
+    remotes: &[S3Remote],
+    config: &ScrubConfig,
+    mut discrepancy: ScrubDiscrepancy,
+) -> Result<(), ScrubError> {
+    if config.repair {
+        let source = remotes
+            .iter()
+            .find(|r| r.name == discrepancy.reference_remote);
+        let target = remotes.iter().find(|r| r.name == discrepancy.remote);
+        if let (Some(source), Some(target)) = (source, target) {
+            match replicate_object(source, target, &discrepancy.key).await {
+                Ok(_) => discrepancy.repaired_at = Some(mongodb::bson::DateTime::now()),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+
+    db.scrub_discrepancies
+        .replace_one(
+            doc! {
+                "remote": &discrepancy.remote,
+                "key": &discrepancy.key,
+                "repaired_at": None::<mongodb::bson::DateTime>,
+            },
+            &discrepancy,
+        )
+        .upsert(true)
+        .await?;
+
+    Ok(())
+}